@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::compare::{self, MetricComparison};
+use crate::metrics::{self, Metric};
+use crate::{PSIResultValues, PSIStatisticResult};
+
+/// Raw values of every run of a page, saved to be compared with a later release.
+#[derive(Serialize, Deserialize)]
+pub struct Baseline {
+    pub url: String,
+    pub strategy: String,
+    pub created_at: String,
+    pub values: PSIResultValues,
+    /// Statistics of the values, same format as the JSON output. Only informative, they are
+    /// recalculated from the values.
+    pub statistics: serde_json::Value,
+}
+
+pub struct BaselineCheck {
+    pub file: String,
+    pub baseline_url: String,
+    pub baseline_strategy: String,
+    pub baseline_created_at: String,
+    /// A is the baseline and B the current run.
+    pub comparison: PSIStatisticResult<MetricComparison>,
+    pub alpha: f64,
+    /// Output names of the metrics that got significantly worse, after the Holm correction.
+    pub regressions: Vec<String>,
+}
+
+pub fn save(path: &str, baseline: &Baseline) {
+    let content = serde_json::to_string_pretty(baseline).unwrap();
+
+    fs::write(path, content).expect("Baseline file should be writable");
+}
+
+pub fn load(path: &str) -> Baseline {
+    let content = fs::read_to_string(path).expect("Baseline file should be readable");
+
+    serde_json::from_str(&content).expect("Baseline file should be a baseline saved by psi-test")
+}
+
+/// URLs are compared parsed, `https://a.com` is the same page as `https://a.com/`.
+fn same_page(a: &str, b: &str) -> bool {
+    match (url::Url::parse(a), url::Url::parse(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// A baseline is only compared with runs of the same page and strategy, the metrics of another
+/// page or strategy would flag or hide regressions that don't exist.
+pub fn check_page(baseline: &Baseline, url: &str, strategy: &str) -> Result<(), String> {
+    if !same_page(&baseline.url, url) {
+        return Err(format!(
+            "Baseline is of {}, not of {}, save a baseline of this page with --save-baseline",
+            baseline.url, url
+        ));
+    }
+    if baseline.strategy != strategy {
+        return Err(format!(
+            "Baseline is of the {} strategy, not of {}, test with --strategy={} or save a {} baseline",
+            baseline.strategy, strategy, baseline.strategy, strategy
+        ));
+    }
+
+    Ok(())
+}
+
+fn is_worse(metric: &Metric, delta: f64) -> bool {
+    if metric.lower_is_better {
+        delta > 0_f64
+    } else {
+        delta < 0_f64
+    }
+}
+
+/// Holm-Bonferroni step-down correction: whether each p-value is rejected at `alpha`, keeping
+/// the chance of any false rejection among them below `alpha`. NaN p-values, e.g. of a metric
+/// without values, are never rejected and don't count as tests.
+fn holm_rejections(p_values: &[f64], alpha: f64) -> Vec<bool> {
+    let mut order = (0..p_values.len())
+        .filter(|index| !p_values[*index].is_nan())
+        .collect::<Vec<_>>();
+    order.sort_by(|a, b| p_values[*a].total_cmp(&p_values[*b]));
+
+    let tests = order.len();
+    let mut rejections = vec![false; p_values.len()];
+
+    for (rank, index) in order.into_iter().enumerate() {
+        if p_values[index] >= alpha / (tests - rank) as f64 {
+            break;
+        }
+
+        rejections[index] = true;
+    }
+
+    rejections
+}
+
+pub fn check(
+    path: &str,
+    baseline: &Baseline,
+    values: &PSIResultValues,
+    alpha: f64,
+) -> BaselineCheck {
+    let comparison = compare::compare(&baseline.values, values);
+    // Every metric is a test, so the p-values are corrected for the number of metrics
+    let rejections = holm_rejections(
+        &comparison
+            .iter()
+            .map(|(_, comparison)| comparison.mann_whitney_p_value)
+            .collect::<Vec<_>>(),
+        alpha,
+    );
+    let regressions = comparison
+        .iter()
+        .zip(rejections)
+        .filter(|((name, comparison), rejected)| {
+            *rejected && is_worse(&metrics::find(name), comparison.delta)
+        })
+        .map(|((name, _), _)| name.to_string())
+        .collect();
+
+    BaselineCheck {
+        file: path.to_string(),
+        baseline_url: baseline.url.clone(),
+        baseline_strategy: baseline.strategy.clone(),
+        baseline_created_at: baseline.created_at.clone(),
+        comparison,
+        alpha,
+        regressions,
+    }
+}
+
+#[cfg(test)]
+mod baseline_tests {
+    use crate::metrics::find;
+
+    #[test]
+    fn check_page() {
+        let baseline = super::Baseline {
+            url: "https://base.vtex.app".to_string(),
+            strategy: "mobile".to_string(),
+            created_at: "2026-10-17T00:00:00Z".to_string(),
+            values: crate::PSIResultValues::from_metrics(
+                &crate::metrics::builtin_metrics(),
+                |_| Vec::new(),
+            ),
+            statistics: serde_json::Value::Null,
+        };
+
+        assert!(super::check_page(&baseline, "https://base.vtex.app/", "mobile").is_ok());
+        assert!(super::check_page(&baseline, "https://base.vtex.app/", "desktop").is_err());
+        assert!(super::check_page(&baseline, "https://other.vtex.app/", "mobile").is_err());
+    }
+
+    #[test]
+    fn is_worse() {
+        assert!(super::is_worse(&find("largest_contentful_paint"), 500_f64));
+        assert!(!super::is_worse(
+            &find("largest_contentful_paint"),
+            -500_f64
+        ));
+        assert!(super::is_worse(&find("score"), -0.1));
+        assert!(!super::is_worse(&find("score"), 0.1));
+    }
+
+    #[test]
+    fn holm_rejections() {
+        assert_eq!(
+            vec![true, false, false, true, false],
+            super::holm_rejections(&[0.01, 0.04, 0.03, 0.005, f64::NAN], 0.05)
+        );
+        // Each one alone is below alpha, not corrected for the number of metrics
+        assert_eq!(
+            vec![false, false],
+            super::holm_rejections(&[0.03, 0.04], 0.05)
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use serde::Deserialize;
+use std::fs;
+
+use crate::budget::{self, Budget};
+use crate::metrics::Metric;
+use crate::Strategy;
+
+/// Strategies of the entries without any, in the order they're tested.
+const DEFAULT_STRATEGIES: [Strategy; 2] = [Strategy::MOBILE, Strategy::DESKTOP];
+
+/// A page of the batch file.
+#[derive(Debug, Clone)]
+pub struct BatchEntry {
+    pub url: String,
+    /// Name of the page, e.g. the template it's an example of, reported in every output row.
+    pub label: Option<String>,
+    /// Group of pages, e.g. "PDP", "PLP" or "home", reported in every output row.
+    pub group: Option<String>,
+    pub strategies: Vec<Strategy>,
+    /// Runs of every strategy of the page, instead of the sampling of the batch.
+    pub runs: Option<i8>,
+    /// Budgets checked for the page on top of the `--budget` file.
+    pub budgets: Vec<Budget>,
+}
+
+impl BatchEntry {
+    /// Entry of a URL of a plain list, tested with both strategies.
+    pub fn new(url: String) -> BatchEntry {
+        BatchEntry {
+            url,
+            label: None,
+            group: None,
+            strategies: DEFAULT_STRATEGIES.to_vec(),
+            runs: None,
+            budgets: Vec::new(),
+        }
+    }
+}
+
+/// A list of a structured entry, a YAML or JSON list, or a `;` separated string, e.g. in a CSV
+/// cell.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum List {
+    Joined(String),
+    Items(Vec<String>),
+}
+
+impl List {
+    fn items(self) -> Vec<String> {
+        let items = match self {
+            List::Joined(joined) => joined.split(';').map(str::to_string).collect(),
+            List::Items(items) => items,
+        };
+
+        items
+            .iter()
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct RawEntry {
+    url: String,
+    label: Option<String>,
+    group: Option<String>,
+    strategies: Option<List>,
+    runs: Option<i8>,
+    #[serde(alias = "budget")]
+    budgets: Option<List>,
+}
+
+fn parse_strategy(url: &str, strategy: &str) -> Result<Strategy, String> {
+    match strategy.to_lowercase().as_str() {
+        "mobile" => Ok(Strategy::MOBILE),
+        "desktop" => Ok(Strategy::DESKTOP),
+        _ => Err(format!(
+            "Strategy `{}` of {} should be mobile or desktop",
+            strategy, url
+        )),
+    }
+}
+
+fn parse_entry(raw_entry: RawEntry, metrics: &[Metric]) -> Result<BatchEntry, String> {
+    let url = raw_entry.url.trim().to_string();
+    let strategies = match raw_entry.strategies.map(List::items) {
+        Some(strategies) if !strategies.is_empty() => strategies
+            .iter()
+            .map(|strategy| parse_strategy(&url, strategy))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => DEFAULT_STRATEGIES.to_vec(),
+    };
+
+    if raw_entry.runs.is_some_and(|runs| runs < 1) {
+        return Err(format!("Runs of {} should be at least 1", url));
+    }
+
+    Ok(BatchEntry {
+        strategies,
+        runs: raw_entry.runs,
+        budgets: raw_entry
+            .budgets
+            .map(List::items)
+            .unwrap_or_default()
+            .iter()
+            .map(|expression| budget::parse_budget(expression, metrics))
+            .collect::<Result<_, _>>()?,
+        label: raw_entry.label.filter(|label| !label.is_empty()),
+        group: raw_entry.group.filter(|group| !group.is_empty()),
+        url,
+    })
+}
+
+fn parse_entries(path: &str, content: &str) -> Result<Vec<RawEntry>, String> {
+    let extension = path.rsplit_once('.').map(|(_, extension)| extension);
+
+    match extension {
+        Some("csv") => csv::Reader::from_reader(content.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(|error| error.to_string()),
+        Some("json") => serde_json::from_str(content).map_err(|error| error.to_string()),
+        Some("yaml" | "yml") => serde_yaml::from_str(content).map_err(|error| error.to_string()),
+        // A plain list of URLs, one per line
+        _ => Ok(content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| RawEntry {
+                url: line.to_string(),
+                label: None,
+                group: None,
+                strategies: None,
+                runs: None,
+                budgets: None,
+            })
+            .collect()),
+    }
+}
+
+/// Batch files are a plain list of URLs, one per line, or a CSV, JSON or YAML list of entries,
+/// by extension, with a `url` and an optional `label`, `group`, `strategies`, `runs` and
+/// `budgets`.
+pub fn load_entries(path: &str, metrics: &[Metric]) -> Vec<BatchEntry> {
+    let content = fs::read_to_string(path).expect("Batch file should be readable");
+
+    parse_entries(path, &content)
+        .map_err(|error| format!("Batch file {} is invalid: {}", path, error))
+        .unwrap()
+        .into_iter()
+        .map(|raw_entry| parse_entry(raw_entry, metrics).unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod batch_input_tests {
+    use crate::metrics::builtin_metrics;
+    use crate::Strategy;
+
+    fn load(path: &str, content: &str) -> Vec<super::BatchEntry> {
+        super::parse_entries(path, content)
+            .unwrap()
+            .into_iter()
+            .map(|raw_entry| super::parse_entry(raw_entry, &builtin_metrics()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn load_entries() {
+        let csv = load(
+            "urls.csv",
+            "url,label,group,strategies,runs,budgets\n\
+             https://a.example/p/shoe,Shoe,PDP,mobile,5,score.median >= 0.8; largest_contentful_paint.p75 <= 2500\n\
+             https://a.example/,,,,,\n",
+        );
+        let yaml = load(
+            "urls.yaml",
+            "- url: https://a.example/p/shoe\n  label: Shoe\n  group: PDP\n  strategies: [mobile]\n  runs: 5\n  budgets:\n    - score.median >= 0.8\n    - largest_contentful_paint.p75 <= 2500\n- url: https://a.example/\n",
+        );
+        let json = load(
+            "urls.json",
+            r#"[{"url": "https://a.example/p/shoe", "label": "Shoe", "group": "PDP", "strategies": ["Mobile"], "runs": 5, "budget": "score.median >= 0.8; largest_contentful_paint.p75 <= 2500"}, {"url": "https://a.example/"}]"#,
+        );
+
+        for entries in [csv, yaml, json] {
+            assert_eq!(2, entries.len());
+            assert_eq!("https://a.example/p/shoe", entries[0].url);
+            assert_eq!(Some("Shoe"), entries[0].label.as_deref());
+            assert_eq!(Some("PDP"), entries[0].group.as_deref());
+            assert_eq!(vec![Strategy::MOBILE], entries[0].strategies);
+            assert_eq!(Some(5), entries[0].runs);
+            assert_eq!(2, entries[0].budgets.len());
+            assert_eq!(None, entries[1].group);
+            assert_eq!(
+                vec![Strategy::MOBILE, Strategy::DESKTOP],
+                entries[1].strategies
+            );
+            assert!(entries[1].budgets.is_empty());
+        }
+
+        let text = load("urls.txt", "https://a.example/\n\nhttps://b.example/\n");
+        assert_eq!(2, text.len());
+        assert_eq!(None, text[1].label);
+    }
+
+    #[test]
+    fn invalid_entry() {
+        let entries =
+            super::parse_entries("urls.json", r#"[{"url": "x", "strategies": "tablet"}]"#).unwrap();
+
+        assert!(
+            super::parse_entry(entries.into_iter().next().unwrap(), &builtin_metrics()).is_err()
+        );
+    }
+}
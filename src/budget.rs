@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::metrics::Metric;
+use crate::statistics::PageStatistics;
+
+const STATISTICS: [&str; 12] = [
+    "mean", "std_dev", "variance", "ci_min", "ci_max", "min", "p25", "median", "p75", "p90", "p95",
+    "max",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Operator {
+    fn check(&self, actual: f64, threshold: f64) -> bool {
+        match *self {
+            Operator::Lt => actual < threshold,
+            Operator::Le => actual <= threshold,
+            Operator::Gt => actual > threshold,
+            Operator::Ge => actual >= threshold,
+        }
+    }
+}
+
+/// A budget expression, e.g. `score.median >= 0.8` or `largest_contentful_paint.p75 <= 2500`.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    pub expression: String,
+    pub metric: String,
+    pub statistic: String,
+    pub operator: Operator,
+    pub threshold: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BudgetResult {
+    pub budget: String,
+    pub actual: f64,
+    pub passed: bool,
+}
+
+#[derive(Deserialize)]
+struct BudgetFile {
+    budgets: Vec<String>,
+}
+
+pub fn parse_budget(expression: &str, metrics: &[Metric]) -> Result<Budget, String> {
+    // Two characters operators first, `<` would also match `<=`
+    let (position, operator, symbol) = [
+        ("<=", Operator::Le),
+        (">=", Operator::Ge),
+        ("<", Operator::Lt),
+        (">", Operator::Gt),
+    ]
+    .iter()
+    .find_map(|(symbol, operator)| {
+        expression
+            .find(symbol)
+            .map(|position| (position, *operator, *symbol))
+    })
+    .ok_or_else(|| format!("Budget `{}` has no <, <=, > or >= operator", expression))?;
+
+    let (metric, statistic) = expression[..position]
+        .trim()
+        .split_once('.')
+        .ok_or_else(|| {
+            format!(
+                "Budget `{}` should start with <metric>.<statistic>",
+                expression
+            )
+        })?;
+
+    if !metrics.iter().any(|known| known.name == metric) {
+        return Err(format!(
+            "Budget `{}` has an unknown metric {}",
+            expression, metric
+        ));
+    }
+    if !STATISTICS.contains(&statistic) {
+        return Err(format!(
+            "Budget `{}` has an unknown statistic {}, expected one of: {}",
+            expression,
+            statistic,
+            STATISTICS.join(", ")
+        ));
+    }
+
+    let threshold = expression[position + symbol.len()..]
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| format!("Budget `{}` threshold should be a number", expression))?;
+
+    Ok(Budget {
+        expression: expression.trim().to_string(),
+        metric: metric.to_string(),
+        statistic: statistic.to_string(),
+        operator,
+        threshold,
+    })
+}
+
+/// Budget files are TOML or JSON, by extension, with a `budgets` list of expressions.
+pub fn load_budgets(path: &str, metrics: &[Metric]) -> Vec<Budget> {
+    let content = fs::read_to_string(path).expect("Budget file should be readable");
+
+    let budget_file: BudgetFile = if path.ends_with(".toml") {
+        toml::from_str(&content).expect("Budget file should be a valid TOML")
+    } else {
+        serde_json::from_str(&content).expect("Budget file should be a valid JSON")
+    };
+
+    budget_file
+        .budgets
+        .iter()
+        .map(|expression| parse_budget(expression, metrics).unwrap())
+        .collect()
+}
+
+fn statistic_value(statistics: &PageStatistics, metric: &str, statistic: &str) -> f64 {
+    let percentiles = statistics.percentiles.metric(metric).unwrap();
+    let confidence_interval = statistics.confidence_interval.metric(metric).unwrap();
+
+    match statistic {
+        "mean" => *statistics.mean.metric(metric).unwrap(),
+        "std_dev" => *statistics.std_deviation.metric(metric).unwrap(),
+        "variance" => *statistics.variance.metric(metric).unwrap(),
+        "ci_min" => confidence_interval.0,
+        "ci_max" => confidence_interval.1,
+        "min" => percentiles.min,
+        "p25" => percentiles.p25,
+        "median" => percentiles.median,
+        "p75" => percentiles.p75,
+        "p90" => percentiles.p90,
+        "p95" => percentiles.p95,
+        "max" => percentiles.max,
+        _ => unreachable!("Statistic validated when parsing the budget"),
+    }
+}
+
+/// Checks every budget against the page statistics. A NaN statistic, e.g. when every run
+/// failed, doesn't pass any budget.
+pub fn evaluate(budgets: &[Budget], statistics: &PageStatistics) -> Vec<BudgetResult> {
+    budgets
+        .iter()
+        .map(|budget| {
+            let actual = statistic_value(statistics, &budget.metric, &budget.statistic);
+
+            BudgetResult {
+                budget: budget.expression.clone(),
+                actual,
+                passed: budget.operator.check(actual, budget.threshold),
+            }
+        })
+        .collect()
+}
+
+/// Results of the budgets of a strategy that wasn't tested, none passes.
+pub fn not_evaluated(budgets: &[Budget]) -> Vec<BudgetResult> {
+    budgets
+        .iter()
+        .map(|budget| BudgetResult {
+            budget: budget.expression.clone(),
+            actual: f64::NAN,
+            passed: false,
+        })
+        .collect()
+}
+
+pub fn all_passed(results: &[BudgetResult]) -> bool {
+    results.iter().all(|result| result.passed)
+}
+
+#[cfg(test)]
+mod budget_tests {
+    use super::Operator;
+    use crate::metrics::builtin_metrics;
+
+    #[test]
+    fn parse_budget() {
+        let metrics = builtin_metrics();
+
+        let budget = super::parse_budget("score.median >= 0.8", &metrics).unwrap();
+        assert_eq!("score", budget.metric);
+        assert_eq!("median", budget.statistic);
+        assert_eq!(Operator::Ge, budget.operator);
+        assert_eq!(0.8, budget.threshold);
+
+        let budget = super::parse_budget("largest_contentful_paint.p75<2500", &metrics).unwrap();
+        assert_eq!(Operator::Lt, budget.operator);
+        assert_eq!(2500_f64, budget.threshold);
+
+        assert!(super::parse_budget("score.median 0.8", &metrics).is_err());
+        assert!(super::parse_budget("lcp.p75 <= 2500", &metrics).is_err());
+        assert!(super::parse_budget("score.p99 >= 0.8", &metrics).is_err());
+        assert!(super::parse_budget("score.median >= fast", &metrics).is_err());
+    }
+
+    #[test]
+    fn check() {
+        assert!(Operator::Le.check(2500_f64, 2500_f64));
+        assert!(!Operator::Lt.check(2500_f64, 2500_f64));
+        assert!(!Operator::Ge.check(f64::NAN, 0.8));
+    }
+
+    #[test]
+    fn not_evaluated() {
+        let budgets = vec![super::parse_budget("score.median >= 0.8", &builtin_metrics()).unwrap()];
+
+        assert!(!super::all_passed(&super::not_evaluated(&budgets)));
+        assert!(super::all_passed(&super::not_evaluated(&[])));
+    }
+}
@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+
+use crate::batch_input::BatchEntry;
+use crate::metrics::Metric;
+use crate::outcome::RunError;
+use crate::{statistics, BatchRow, BatchStrategyResult, PSIResultValues};
+
+/// Raw results of a strategy of a finished batch page.
+#[derive(Serialize, Deserialize)]
+struct StrategyEntry {
+    success_runs: usize,
+    /// Values of every successful run of each metric.
+    values: PSIResultValues,
+    failed_runs: Vec<(usize, RunError)>,
+}
+
+/// A finished batch page, a line of the checkpoint file.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    mobile: Option<StrategyEntry>,
+    desktop: Option<StrategyEntry>,
+}
+
+impl StrategyEntry {
+    fn new(result: &BatchStrategyResult) -> StrategyEntry {
+        StrategyEntry {
+            success_runs: result.success_runs,
+            values: result.values.clone(),
+            failed_runs: result.failed_runs.clone(),
+        }
+    }
+
+    /// Metrics the checkpoint doesn't have, e.g. a `--metric` added when resuming, have no
+    /// values and NaN statistics.
+    fn into_result(self, confidence_level: f64, metrics: &[Metric]) -> BatchStrategyResult {
+        let values = PSIResultValues::from_metrics(metrics, |metric| {
+            self.values
+                .metric(&metric.name)
+                .cloned()
+                .unwrap_or_default()
+        });
+
+        BatchStrategyResult {
+            success_runs: self.success_runs,
+            failed_runs: self.failed_runs,
+            statistics: statistics::calculate_statistics(&values, confidence_level),
+            values,
+        }
+    }
+}
+
+/// JSON Lines file recording every finished page of a batch as soon as its row is written, so
+/// an interrupted batch can be resumed without testing those pages again.
+pub struct Checkpoint {
+    path: String,
+    file: File,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint file, starting a new one unless resuming. Returns the rows of the
+    /// pages already finished, with the statistics of `metrics` recalculated at
+    /// `confidence_level`.
+    pub fn open(
+        path: &str,
+        resume: bool,
+        confidence_level: f64,
+        metrics: &[Metric],
+    ) -> (Checkpoint, Vec<BatchRow>) {
+        let rows = if resume {
+            let content = fs::read_to_string(path).unwrap_or_default();
+
+            content
+                .lines()
+                // The last line is incomplete when the batch stopped while writing it
+                .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+                // The entry of the batch file is set when resuming, by URL
+                .map(|entry| BatchRow {
+                    entry: BatchEntry::new(entry.url),
+                    mobile: entry
+                        .mobile
+                        .map(|mobile| mobile.into_result(confidence_level, metrics)),
+                    desktop: entry
+                        .desktop
+                        .map(|desktop| desktop.into_result(confidence_level, metrics)),
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let mut checkpoint = Checkpoint {
+            path: path.to_string(),
+            file: File::create(path).expect("Checkpoint file should be writable"),
+        };
+        // Rewritten without the incomplete line, if any
+        for row in &rows {
+            checkpoint.record(row);
+        }
+
+        (checkpoint, rows)
+    }
+
+    pub fn record(&mut self, row: &BatchRow) {
+        let entry = Entry {
+            url: row.entry.url.clone(),
+            mobile: row.mobile.as_ref().map(StrategyEntry::new),
+            desktop: row.desktop.as_ref().map(StrategyEntry::new),
+        };
+        let line = serde_json::to_string(&entry).unwrap() + "\n";
+
+        self.file
+            .write_all(line.as_bytes())
+            .expect("Checkpoint file should be writable");
+    }
+
+    /// The batch finished, there's nothing left to resume.
+    pub fn remove(self) {
+        fs::remove_file(&self.path).expect("Checkpoint file should be removable");
+    }
+}
+
+#[cfg(test)]
+mod checkpoint_tests {
+    use std::io::Write;
+
+    use crate::batch_input::BatchEntry;
+    use crate::outcome::RunError;
+    use crate::{metrics, BatchRow, BatchStrategyResult, PSIResultValues};
+
+    fn strategy_result(values: &[f64], failed_runs: Vec<(usize, RunError)>) -> BatchStrategyResult {
+        let values =
+            PSIResultValues::from_metrics(&metrics::builtin_metrics(), |_| values.to_vec());
+
+        BatchStrategyResult {
+            success_runs: values.metric("score").unwrap().len(),
+            failed_runs,
+            statistics: crate::statistics::calculate_statistics(&values, 0.95),
+            values,
+        }
+    }
+
+    #[test]
+    fn resume() {
+        let path =
+            std::env::temp_dir().join(format!("psi-test-checkpoint-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let (mut checkpoint, rows) =
+            super::Checkpoint::open(path, false, 0.95, &metrics::builtin_metrics());
+        assert!(rows.is_empty());
+        checkpoint.record(&BatchRow {
+            entry: BatchEntry::new("https://a.example/".to_string()),
+            mobile: Some(strategy_result(&[0.8, 0.9], Vec::new())),
+            desktop: Some(strategy_result(&[0.7], vec![(1, RunError::Timeout)])),
+        });
+        // Interrupted while writing the second page
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(br#"{"url": "https://b.exa"#)
+            .unwrap();
+
+        // Resumed with a metric the checkpoint doesn't have
+        let mut resumed_metrics = metrics::builtin_metrics();
+        resumed_metrics.push(metrics::Metric::custom("network-rtt"));
+        let (checkpoint, rows) = super::Checkpoint::open(path, true, 0.95, &resumed_metrics);
+
+        assert_eq!(1, rows.len());
+        assert_eq!("https://a.example/", rows[0].entry.url);
+        let mobile = rows[0].mobile.as_ref().unwrap();
+        assert_eq!(2, mobile.success_runs);
+        assert!((mobile.statistics.mean.metric("score").unwrap() - 0.85).abs() < 1e-9);
+        assert_eq!(1, rows[0].desktop.as_ref().unwrap().failed_runs.len());
+        assert!(mobile
+            .statistics
+            .mean
+            .metric("network-rtt")
+            .unwrap()
+            .is_nan());
+        assert_eq!(1, std::fs::read_to_string(path).unwrap().lines().count());
+
+        checkpoint.remove();
+        assert!(!std::path::Path::new(path).exists());
+    }
+}
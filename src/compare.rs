@@ -0,0 +1,94 @@
+use serde::Serialize;
+use std::fs;
+
+use crate::statistics;
+use crate::{PSIResultValues, PSIStatisticResult};
+
+pub const DEFAULT_ALPHA: f64 = 0.05_f64;
+
+/// Difference of a metric between sample A and sample B, B being the candidate.
+#[derive(Debug, Serialize)]
+pub struct MetricComparison {
+    pub mean_a: f64,
+    pub mean_b: f64,
+    /// mean_b - mean_a
+    pub delta: f64,
+    /// delta relative to mean_a, in percent. `None` when mean_a is 0, common for CLS and TBT.
+    pub percent_change: Option<f64>,
+    pub welch_p_value: f64,
+    pub mann_whitney_p_value: f64,
+    pub cohens_d: f64,
+    pub cliffs_delta: f64,
+}
+
+pub fn compare_metric(a: &[f64], b: &[f64]) -> MetricComparison {
+    let mean_a = statistics::mean(a);
+    let mean_b = statistics::mean(b);
+    let delta = mean_b - mean_a;
+
+    MetricComparison {
+        mean_a,
+        mean_b,
+        delta,
+        percent_change: (mean_a != 0_f64).then(|| delta / mean_a * 100_f64),
+        welch_p_value: statistics::welch_t_test(a, b),
+        mann_whitney_p_value: statistics::mann_whitney_u_test(a, b),
+        cohens_d: statistics::cohens_d(a, b),
+        cliffs_delta: statistics::cliffs_delta(a, b),
+    }
+}
+
+/// Compares the metrics of A, B values of a metric missing in B are empty.
+pub fn compare(a: &PSIResultValues, b: &PSIResultValues) -> PSIStatisticResult<MetricComparison> {
+    a.map_metrics(|name, values| {
+        compare_metric(
+            values,
+            b.metric(name).map_or(&[], |values| values.as_slice()),
+        )
+    })
+}
+
+/// Compare arguments can be a page URL, to be tested, a baseline saved by `--save-baseline` or
+/// a JSON file with the raw values of each metric, e.g.
+/// `{"score": [0.81, 0.79], "largest_contentful_paint": [2400, 2550], ...}`.
+pub fn is_page_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+pub fn load_samples(path: &str) -> PSIResultValues {
+    let content = fs::read_to_string(path).expect("Samples file should be readable");
+
+    let mut json: serde_json::Value =
+        serde_json::from_str(&content).expect("Samples file should be a valid JSON");
+
+    // Baseline files keep the values in a field
+    if let Some(values) = json.get_mut("values") {
+        json = values.take();
+    }
+
+    serde_json::from_value(json).expect("Samples file should contain the values of each metric")
+}
+
+#[cfg(test)]
+mod compare_tests {
+    #[test]
+    fn compare_metric() {
+        let comparison = super::compare_metric(
+            &[2000_f64, 2100_f64, 2050_f64],
+            &[2500_f64, 2600_f64, 2550_f64],
+        );
+
+        assert_eq!(500_f64, comparison.delta);
+        assert!((comparison.percent_change.unwrap() - 24.39).abs() < 0.01);
+        assert!(comparison.welch_p_value < 0.05);
+        assert_eq!(1_f64, comparison.cliffs_delta);
+    }
+
+    #[test]
+    fn zero_baseline_mean() {
+        let comparison = super::compare_metric(&[0_f64, 0_f64, 0_f64], &[0.1, 0.05, 0.08]);
+
+        assert!(comparison.delta > 0_f64);
+        assert_eq!(None, comparison.percent_change);
+    }
+}
@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+
+/// CrUX reports CLS multiplied by 100, e.g. 5 is a CLS of 0.05.
+const CLS_SCALE: f64 = 100_f64;
+
+#[derive(Deserialize, Debug)]
+struct Distribution {
+    proportion: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct CrUXMetric {
+    percentile: f64,
+    /// FAST, AVERAGE and SLOW buckets, in this order.
+    #[serde(default)]
+    distributions: Vec<Distribution>,
+    category: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CrUXMetrics {
+    #[serde(rename = "LARGEST_CONTENTFUL_PAINT_MS")]
+    largest_contentful_paint: Option<CrUXMetric>,
+
+    #[serde(rename = "CUMULATIVE_LAYOUT_SHIFT_SCORE")]
+    cumulative_layout_shift: Option<CrUXMetric>,
+
+    #[serde(rename = "INTERACTION_TO_NEXT_PAINT")]
+    interaction_to_next_paint: Option<CrUXMetric>,
+
+    #[serde(rename = "FIRST_CONTENTFUL_PAINT_MS")]
+    first_contentful_paint: Option<CrUXMetric>,
+
+    #[serde(rename = "EXPERIMENTAL_TIME_TO_FIRST_BYTE")]
+    time_to_first_byte: Option<CrUXMetric>,
+}
+
+/// `loadingExperience` or `originLoadingExperience` of a PSI response. Without enough real-user
+/// traffic only `initial_url` is present.
+#[derive(Deserialize, Debug)]
+pub struct LoadingExperience {
+    id: Option<String>,
+    metrics: Option<CrUXMetrics>,
+    overall_category: Option<String>,
+    /// The page has no data, the metrics of `loadingExperience` are the ones of its origin. It's
+    /// always the case of the runs, their URL has a cache buster CrUX never saw.
+    #[serde(default)]
+    origin_fallback: bool,
+}
+
+/// p75 of the last 28 days of real-user data and the share of FAST, AVERAGE and SLOW
+/// experiences.
+#[derive(Debug, Serialize)]
+pub struct FieldMetric {
+    pub p75: f64,
+    pub category: Option<String>,
+    pub fast: f64,
+    pub average: f64,
+    pub slow: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldExperience {
+    pub id: Option<String>,
+    pub overall_category: Option<String>,
+    pub largest_contentful_paint: Option<FieldMetric>,
+    pub cumulative_layout_shift: Option<FieldMetric>,
+    pub interaction_to_next_paint: Option<FieldMetric>,
+    pub first_contentful_paint: Option<FieldMetric>,
+    pub time_to_first_byte: Option<FieldMetric>,
+}
+
+/// CrUX data of the page URL and of its whole origin, `None` when CrUX has no data for them.
+#[derive(Debug, Serialize)]
+pub struct FieldData {
+    pub page: Option<FieldExperience>,
+    pub origin: Option<FieldExperience>,
+}
+
+fn field_metric(metric: Option<&CrUXMetric>, scale: f64) -> Option<FieldMetric> {
+    metric.map(|metric| {
+        let proportion = |bucket: usize| {
+            metric
+                .distributions
+                .get(bucket)
+                .map_or(f64::NAN, |distribution| distribution.proportion)
+        };
+
+        FieldMetric {
+            p75: metric.percentile / scale,
+            category: metric.category.clone(),
+            fast: proportion(0),
+            average: proportion(1),
+            slow: proportion(2),
+        }
+    })
+}
+
+fn field_experience(loading_experience: Option<&LoadingExperience>) -> Option<FieldExperience> {
+    let loading_experience = loading_experience?;
+    let metrics = loading_experience.metrics.as_ref()?;
+
+    Some(FieldExperience {
+        id: loading_experience.id.clone(),
+        overall_category: loading_experience.overall_category.clone(),
+        largest_contentful_paint: field_metric(metrics.largest_contentful_paint.as_ref(), 1_f64),
+        cumulative_layout_shift: field_metric(metrics.cumulative_layout_shift.as_ref(), CLS_SCALE),
+        interaction_to_next_paint: field_metric(metrics.interaction_to_next_paint.as_ref(), 1_f64),
+        first_contentful_paint: field_metric(metrics.first_contentful_paint.as_ref(), 1_f64),
+        time_to_first_byte: field_metric(metrics.time_to_first_byte.as_ref(), 1_f64),
+    })
+}
+
+pub fn field_data(
+    loading_experience: Option<&LoadingExperience>,
+    origin_loading_experience: Option<&LoadingExperience>,
+) -> FieldData {
+    FieldData {
+        // Origin data isn't reported as the one of the page
+        page: field_experience(
+            loading_experience.filter(|loading_experience| !loading_experience.origin_fallback),
+        ),
+        origin: field_experience(origin_loading_experience),
+    }
+}
+
+#[cfg(test)]
+mod field_data_tests {
+    use super::LoadingExperience;
+
+    #[test]
+    fn field_data() {
+        let page = serde_json::from_str::<LoadingExperience>(
+            r#"{
+                "id": "https://example.com/",
+                "metrics": {
+                    "LARGEST_CONTENTFUL_PAINT_MS": {
+                        "percentile": 2300,
+                        "distributions": [
+                            {"min": 0, "max": 2500, "proportion": 0.78},
+                            {"min": 2500, "max": 4000, "proportion": 0.15},
+                            {"min": 4000, "proportion": 0.07}
+                        ],
+                        "category": "FAST"
+                    },
+                    "CUMULATIVE_LAYOUT_SHIFT_SCORE": {
+                        "percentile": 12,
+                        "distributions": [
+                            {"min": 0, "max": 10, "proportion": 0.7},
+                            {"min": 10, "max": 25, "proportion": 0.2},
+                            {"min": 25, "proportion": 0.1}
+                        ],
+                        "category": "AVERAGE"
+                    }
+                },
+                "overall_category": "AVERAGE"
+            }"#,
+        )
+        .unwrap();
+        let origin =
+            serde_json::from_str::<LoadingExperience>(r#"{"initial_url": "https://example.com/"}"#)
+                .unwrap();
+
+        let field_data = super::field_data(Some(&page), Some(&origin));
+        let page = field_data.page.unwrap();
+        let largest_contentful_paint = page.largest_contentful_paint.unwrap();
+
+        assert_eq!(2300_f64, largest_contentful_paint.p75);
+        assert_eq!(0.15, largest_contentful_paint.average);
+        assert_eq!(0.12, page.cumulative_layout_shift.unwrap().p75);
+        assert!(page.interaction_to_next_paint.is_none());
+        assert!(field_data.origin.is_none());
+
+        let origin_fallback = serde_json::from_str::<LoadingExperience>(
+            r#"{
+                "id": "https://example.com/?__v=1",
+                "metrics": {"LARGEST_CONTENTFUL_PAINT_MS": {"percentile": 2300, "category": "FAST"}},
+                "overall_category": "FAST",
+                "initial_url": "https://example.com/?__v=1",
+                "origin_fallback": true
+            }"#,
+        )
+        .unwrap();
+
+        assert!(super::field_data(Some(&origin_fallback), None)
+            .page
+            .is_none());
+    }
+}
@@ -1,15 +1,41 @@
 use clap::{Arg, Command};
+use futures::StreamExt;
 use reqwest::Error;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::ExitCode;
 
+use outcome::RunError;
+
+mod baseline;
+mod batch_input;
+mod budget;
+mod checkpoint;
+mod compare;
+mod field_data;
+mod metrics;
+mod opportunities;
+mod outcome;
+mod outliers;
+mod output;
 mod printer;
+mod rate_limiter;
+mod raw_output;
+mod resources;
+mod responses;
+mod sampling;
 mod statistics;
 mod tester;
+mod third_parties;
 mod utils;
 
 const SAMPLE: i8 = 20;
+const ADAPTIVE_MIN_RUNS: i8 = 5;
+const ADAPTIVE_MAX_RUNS: i8 = 50;
+/// Batch pages tested at the same time.
+const DEFAULT_URL_CONCURRENCY: usize = 1;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
 pub enum Strategy {
     MOBILE,
     DESKTOP,
@@ -25,202 +51,675 @@ impl std::fmt::Display for Strategy {
 }
 
 #[derive(Deserialize, Debug)]
-struct Audit {
-    #[serde(rename = "numericValue")]
-    numeric_value: f64,
-}
+struct AuditDetails {
+    #[serde(rename = "type")]
+    kind: Option<String>,
 
-#[derive(Deserialize, Debug)]
-struct Audits {
-    #[serde(rename = "cumulative-layout-shift")]
-    cumulative_layout_shift: Audit,
+    #[serde(rename = "overallSavingsMs")]
+    overall_savings_ms: Option<f64>,
 
-    #[serde(rename = "first-contentful-paint")]
-    first_contentful_paint: Audit,
+    #[serde(rename = "overallSavingsBytes")]
+    overall_savings_bytes: Option<f64>,
 
-    #[serde(rename = "bootup-time")]
-    js_execution_time: Audit,
+    #[serde(default)]
+    items: Vec<serde_json::Value>,
+}
 
-    #[serde(rename = "largest-contentful-paint")]
-    largest_contentful_paint: Audit,
+#[derive(Deserialize, Debug)]
+struct Audit {
+    title: Option<String>,
+    score: Option<f64>,
 
-    #[serde(rename = "speed-index")]
-    speed_index: Audit,
+    #[serde(rename = "scoreDisplayMode")]
+    score_display_mode: Option<String>,
 
-    #[serde(rename = "interactive")]
-    time_to_interactive: Audit,
+    #[serde(rename = "numericValue")]
+    numeric_value: Option<f64>,
 
-    #[serde(rename = "total-blocking-time")]
-    total_blocking_time: Audit,
+    details: Option<AuditDetails>,
 }
 
 #[derive(Deserialize, Debug)]
-struct Category {
-    score: f64,
+struct AuditRef {
+    id: String,
+    group: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
-struct Categories {
-    performance: Category,
+struct Category {
+    /// `null` when Lighthouse failed to audit the category.
+    score: Option<f64>,
+
+    #[serde(rename = "auditRefs", default)]
+    audit_refs: Vec<AuditRef>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct LHResult {
-    audits: Audits,
-    categories: Categories,
+    audits: HashMap<String, Audit>,
+    categories: HashMap<String, Category>,
+
+    #[serde(rename = "fetchTime")]
+    fetch_time: Option<String>,
+
+    #[serde(rename = "lighthouseVersion")]
+    lighthouse_version: Option<String>,
+
+    #[serde(rename = "finalUrl")]
+    final_url: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
-struct PSIResult {
+pub struct PSIResult {
     #[serde(rename = "lighthouseResult")]
     lighthouse_result: LHResult,
-}
 
-pub struct PSIResultValues {
-    cumulative_layout_shift: Vec<f64>,
-    first_contentful_paint: Vec<f64>,
-    js_execution_time: Vec<f64>,
-    largest_contentful_paint: Vec<f64>,
-    speed_index: Vec<f64>,
-    time_to_interactive: Vec<f64>,
-    total_blocking_time: Vec<f64>,
-    score: Vec<f64>,
+    #[serde(rename = "analysisUTCTimestamp")]
+    analysis_utc_timestamp: Option<String>,
+
+    #[serde(rename = "loadingExperience")]
+    loading_experience: Option<field_data::LoadingExperience>,
+
+    #[serde(rename = "originLoadingExperience")]
+    origin_loading_experience: Option<field_data::LoadingExperience>,
 }
 
-#[derive(Debug)]
+/// A value of each metric of the registry, e.g. its mean, in the registry order. Serialized as
+/// a map by metric output name.
+#[derive(Debug, Clone)]
 pub struct PSIStatisticResult<T> {
-    cumulative_layout_shift: T,
-    first_contentful_paint: T,
-    js_execution_time: T,
-    largest_contentful_paint: T,
-    speed_index: T,
-    time_to_interactive: T,
-    total_blocking_time: T,
-    score: T,
+    metrics: Vec<(String, T)>,
+}
+
+/// Values of every successful run of each metric.
+pub type PSIResultValues = PSIStatisticResult<Vec<f64>>;
+
+impl<T> PSIStatisticResult<T> {
+    pub fn from_metrics(
+        metrics: &[metrics::Metric],
+        f: impl Fn(&metrics::Metric) -> T,
+    ) -> PSIStatisticResult<T> {
+        PSIStatisticResult {
+            metrics: metrics
+                .iter()
+                .map(|metric| (metric.name.clone(), f(metric)))
+                .collect(),
+        }
+    }
+
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> PSIStatisticResult<U> {
+        self.map_metrics(|_, value| f(value))
+    }
+
+    /// Same as `map`, also passing the metric output name.
+    pub fn map_metrics<U>(&self, f: impl Fn(&str, &T) -> U) -> PSIStatisticResult<U> {
+        PSIStatisticResult {
+            metrics: self
+                .metrics
+                .iter()
+                .map(|(name, value)| (name.clone(), f(name, value)))
+                .collect(),
+        }
+    }
+
+    /// Statistic of a metric by its output name, e.g. largest_contentful_paint.
+    pub fn metric(&self, name: &str) -> Option<&T> {
+        self.metrics
+            .iter()
+            .find(|(metric, _)| metric == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.metrics
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+}
+
+impl<T: Serialize> Serialize for PSIStatisticResult<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for PSIStatisticResult<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut metrics = HashMap::<String, T>::deserialize(deserializer)?
+            .into_iter()
+            .collect::<Vec<_>>();
+        metrics.sort_by(|(a, _), (b, _)| {
+            (metrics::position(a), a.as_str()).cmp(&(metrics::position(b), b.as_str()))
+        });
+
+        Ok(PSIStatisticResult { metrics })
+    }
+}
+
+fn print_failed_budgets(url: &str, strategy: Strategy, budget_results: &[budget::BudgetResult]) {
+    for result in budget_results.iter().filter(|result| !result.passed) {
+        println!(
+            "Budget failed for {url} ({strategy}): {budget}, actual {actual}",
+            url = url,
+            strategy = strategy,
+            budget = result.budget,
+            actual = result.actual
+        );
+    }
+}
+
+fn print_failed_runs(url: &str, strategy: Strategy, page_audits: &tester::PageAudits) {
+    for (index, error) in page_audits.failures() {
+        println!(
+            "Run {index} failed for {url} ({strategy}): {error}",
+            index = index,
+            url = url,
+            strategy = strategy,
+            error = error
+        );
+    }
+}
+
+/// Statistics of a batch page for a strategy, NaN when every run failed.
+pub struct BatchStrategyResult {
+    success_runs: usize,
+    failed_runs: Vec<(usize, RunError)>,
+    statistics: statistics::PageStatistics,
+    values: PSIResultValues,
+}
+
+/// A row of the batch output. A strategy is `None` when the entry doesn't have it.
+pub struct BatchRow {
+    entry: batch_input::BatchEntry,
+    mobile: Option<BatchStrategyResult>,
+    desktop: Option<BatchStrategyResult>,
+}
+
+impl BatchRow {
+    fn result(&self, strategy: Strategy) -> &Option<BatchStrategyResult> {
+        match strategy {
+            Strategy::MOBILE => &self.mobile,
+            Strategy::DESKTOP => &self.desktop,
+        }
+    }
+
+    /// Some run failed for a reason a retest may fix, e.g. throttling, not a page Lighthouse
+    /// can't audit.
+    fn is_retryable(&self) -> bool {
+        [&self.mobile, &self.desktop]
+            .iter()
+            .filter_map(|result| result.as_ref())
+            .flat_map(|result| &result.failed_runs)
+            .any(|(_, error)| tester::is_retryable(error))
+    }
+
+    fn has_failures(&self) -> bool {
+        self.entry.strategies.iter().any(|strategy| {
+            self.result(*strategy)
+                .as_ref()
+                .is_none_or(|result| !result.failed_runs.is_empty())
+        })
+    }
+}
+
+fn batch_strategy_result(
+    page_audits: &tester::PageAudits,
+    confidence_level: f64,
+) -> BatchStrategyResult {
+    BatchStrategyResult {
+        success_runs: page_audits.success_runs(),
+        failed_runs: page_audits
+            .failures()
+            .into_iter()
+            .map(|(index, error)| (index, error.clone()))
+            .collect(),
+        statistics: statistics::calculate_statistics(&page_audits.values(), confidence_level),
+        values: page_audits.values(),
+    }
+}
+
+/// Checks the budgets, the batch ones and the ones of the entry, against every strategy of the
+/// entry, on the runs that succeeded. The budgets of a strategy without any successful run, or
+/// not tested, fail.
+fn evaluate_batch_budgets(row: &BatchRow, budgets: &[budget::Budget]) -> bool {
+    let mut budgets_passed = true;
+
+    for strategy in &row.entry.strategies {
+        let budget_results = [budgets, &row.entry.budgets]
+            .iter()
+            .flat_map(|budgets| match row.result(*strategy) {
+                Some(result) => budget::evaluate(budgets, &result.statistics),
+                None => budget::not_evaluated(budgets),
+            })
+            .collect::<Vec<_>>();
+
+        print_failed_budgets(&row.entry.url, *strategy, &budget_results);
+
+        budgets_passed &= budget::all_passed(&budget_results);
+    }
+
+    budgets_passed
+}
+
+/// The runs of every strategy of a tested batch entry, and its row.
+struct TestedPage {
+    page_audits: Vec<(Strategy, tester::PageAudits)>,
+    row: BatchRow,
+}
+
+impl TestedPage {
+    fn new(
+        entry: &batch_input::BatchEntry,
+        page_audits: Vec<(Strategy, tester::PageAudits)>,
+        confidence_level: f64,
+    ) -> TestedPage {
+        let strategy_result = |strategy: Strategy| {
+            page_audits
+                .iter()
+                .find(|(tested_strategy, _)| *tested_strategy == strategy)
+                .map(|(_, page_audits)| batch_strategy_result(page_audits, confidence_level))
+        };
+        let row = BatchRow {
+            entry: entry.clone(),
+            mobile: strategy_result(Strategy::MOBILE),
+            desktop: strategy_result(Strategy::DESKTOP),
+        };
+
+        TestedPage { page_audits, row }
+    }
 }
 
+/// Tests every strategy of a batch entry, with its own number of runs when it has one, one
+/// after the other or concurrently.
 async fn batch_tests(
-    url: &str,
-    token: &str,
-    number_of_runs: i8,
-    printer: &mut printer::CSVPrinter,
-) -> bool {
-    let mobile_page_result = tester::get_page_audits(url, token, number_of_runs, Strategy::MOBILE)
+    entry: &batch_input::BatchEntry,
+    tester: &tester::Tester,
+    sampling: &sampling::Sampling,
+    confidence_level: f64,
+    concurrent_strategies: bool,
+) -> TestedPage {
+    let entry_sampling = entry.runs.map(sampling::Sampling::Fixed);
+    let sampling = entry_sampling.as_ref().unwrap_or(sampling);
+    let url = entry.url.as_str();
+
+    let page_audits = if concurrent_strategies {
+        futures::future::join_all(entry.strategies.iter().map(|strategy| async move {
+            let (page_audits, _) =
+                sampling::sample_page(tester, url, sampling, *strategy, Some(entry)).await;
+
+            (*strategy, page_audits)
+        }))
         .await
-        .unwrap();
+    } else {
+        let mut page_audits = Vec::new();
+
+        for strategy in &entry.strategies {
+            let (strategy_page_audits, _) =
+                sampling::sample_page(tester, url, sampling, *strategy, Some(entry)).await;
+
+            page_audits.push((*strategy, strategy_page_audits));
+        }
+
+        page_audits
+    };
+
     // Handle if some test failed
-    if mobile_page_result.score.len() != number_of_runs as usize {
-        return false;
+    for (strategy, page_audits) in &page_audits {
+        print_failed_runs(url, *strategy, page_audits);
     }
 
-    let desktop_page_result =
-        tester::get_page_audits(url, token, number_of_runs, Strategy::DESKTOP)
-            .await
-            .unwrap();
-    // Handle if some test failed
-    if desktop_page_result.score.len() != number_of_runs as usize {
-        return false;
+    TestedPage::new(entry, page_audits, confidence_level)
+}
+
+/// Runs again the failed runs of a strategy a retest may fix, in place, the successful runs
+/// are kept. `attempt` is the retest of the page, starting at 1.
+async fn retest_failed_runs(
+    entry: &batch_input::BatchEntry,
+    tester: &tester::Tester,
+    strategy: Strategy,
+    page_audits: &mut tester::PageAudits,
+    attempt: usize,
+) {
+    let failed_runs = page_audits
+        .failures()
+        .into_iter()
+        .filter(|(_, error)| tester::is_retryable(error))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    if failed_runs.is_empty() {
+        return;
+    }
+
+    let retest = tester
+        .get_page_audits(&entry.url, failed_runs.len() as i8, strategy)
+        .await;
+
+    for (index, run) in failed_runs.iter().zip(retest.runs) {
+        page_audits.runs[*index] = run;
+    }
+
+    if let Some(raw_output) = tester.raw_output() {
+        raw_output.write_retested_runs(
+            &entry.url,
+            strategy,
+            page_audits,
+            &failed_runs,
+            attempt,
+            Some(entry),
+        );
     }
 
-    let mobile_page_mean = statistics::calculate_mean(&mobile_page_result, number_of_runs);
-    let mobile_page_median = statistics::median(&mobile_page_result.score);
+    print_failed_runs(&entry.url, strategy, page_audits);
+}
 
-    let desktop_page_mean = statistics::calculate_mean(&desktop_page_result, number_of_runs);
-    let desktop_page_median = statistics::median(&desktop_page_result.score);
+/// Retests the failed runs of every strategy of a tested page, one strategy after the other or
+/// concurrently. `attempt` is the retest of the page, starting at 1.
+async fn batch_retests(
+    mut page: TestedPage,
+    tester: &tester::Tester,
+    attempt: usize,
+    confidence_level: f64,
+    concurrent_strategies: bool,
+) -> TestedPage {
+    let entry = page.row.entry.clone();
 
-    let _x = printer.write_line(printer::Row {
-        url,
-        d_mean: desktop_page_mean.score,
-        d_median: desktop_page_median,
-        m_mean: mobile_page_mean.score,
-        m_median: mobile_page_median,
-    });
+    if concurrent_strategies {
+        futures::future::join_all(page.page_audits.iter_mut().map(|(strategy, page_audits)| {
+            retest_failed_runs(&entry, tester, *strategy, page_audits, attempt)
+        }))
+        .await;
+    } else {
+        for (strategy, page_audits) in page.page_audits.iter_mut() {
+            retest_failed_runs(&entry, tester, *strategy, page_audits, attempt).await;
+        }
+    }
 
-    let _x = printer.flush();
+    TestedPage::new(&entry, page.page_audits, confidence_level)
+}
 
-    true
+/// Pages finished, written to the output and the checkpoint, pages waiting for a retest of their
+/// failed runs, and whether budgets of the finished pages passed so far.
+struct BatchProgress<'a> {
+    batch_printer: &'a mut printer::BatchPrinter,
+    budgets: &'a [budget::Budget],
+    checkpoint: checkpoint::Checkpoint,
+    finished_urls: Vec<String>,
+    failed_pages: Vec<TestedPage>,
+    budgets_passed: bool,
 }
 
-async fn run_batch_tests(filename: &str, token: &str, number_of_runs: i8) -> bool {
-    let urls = utils::read_lines(filename);
-    let mut failed_urls: Vec<String> = Vec::new();
+impl<'a> BatchProgress<'a> {
+    /// Starts the batch progress. When resuming, the rows of the pages finished before are
+    /// written again from the checkpoint, unless the output already has them, with the entry of
+    /// the batch file having their URL, and those pages aren't tested.
+    fn new(
+        batch_printer: &'a mut printer::BatchPrinter,
+        (checkpoint, finished_rows): (checkpoint::Checkpoint, Vec<BatchRow>),
+        entries: &[batch_input::BatchEntry],
+        budgets: &'a [budget::Budget],
+        finished_rows_written: bool,
+    ) -> BatchProgress<'a> {
+        let mut progress = BatchProgress {
+            batch_printer,
+            budgets,
+            checkpoint,
+            finished_urls: Vec::new(),
+            failed_pages: Vec::new(),
+            budgets_passed: true,
+        };
 
-    let mut csv_printer = printer::CSVPrinter::new();
+        for mut row in finished_rows {
+            row.entry = match entries.iter().find(|entry| entry.url == row.entry.url) {
+                Some(entry) => entry.clone(),
+                None => {
+                    println!(
+                        "{url} of the checkpoint isn't in the batch file anymore, left out",
+                        url = row.entry.url
+                    );
+                    continue;
+                }
+            };
+            println!("Resuming {url} from checkpoint", url = row.entry.url);
 
-    for url in urls.map_while(Result::ok) {
-        println!("Testing {url}", url = url);
+            progress.finished_urls.push(row.entry.url.clone());
+            if finished_rows_written {
+                progress.budgets_passed &= evaluate_batch_budgets(&row, budgets);
+            } else {
+                progress.write_row(&row);
+            }
+        }
 
-        let test_finished = batch_tests(&url, token, number_of_runs, &mut csv_printer).await;
+        progress
+    }
 
-        if !test_finished {
-            failed_urls.push(url.clone());
+    /// Writes the row of the page, unless it has failed runs and will be retested. Replayed
+    /// runs are never retested.
+    fn record(&mut self, page: TestedPage, replaying: bool) {
+        if page.row.has_failures() && page.row.is_retryable() && !replaying {
+            self.failed_pages.push(page);
+        } else {
+            self.finish_row(&page.row);
         }
     }
 
-    // Handle failed urls until failed_urls list is empty
-    for qtt in 0..2 {
-        let urls_size = failed_urls.len();
+    /// Budgets are checked once the page is finished, a failed run retested successfully
+    /// doesn't fail them.
+    fn write_row(&mut self, row: &BatchRow) {
+        self.budgets_passed &= evaluate_batch_budgets(row, self.budgets);
+        self.batch_printer.write_row(row);
+    }
 
-        for url_idx in 0..urls_size {
-            // from last to first
-            let idx = (urls_size - 1) - url_idx;
-            let url = failed_urls[idx].clone();
+    fn finish_row(&mut self, row: &BatchRow) {
+        self.write_row(row);
+        self.checkpoint.record(row);
+    }
+}
 
-            println!("Retesting {url} {qtt}x", url = url, qtt = qtt);
+/// Tests up to `url_concurrency` pages at the same time, every request still goes through the
+/// rate limit and the concurrency limit of the tester. Rows are written in the order of the
+/// batch file.
+async fn run_batch_tests(
+    entries: &[batch_input::BatchEntry],
+    tester: &tester::Tester,
+    sampling: &sampling::Sampling,
+    confidence_level: f64,
+    url_concurrency: usize,
+    mut progress: BatchProgress<'_>,
+) -> bool {
+    let entries = entries
+        .iter()
+        .filter(|entry| !progress.finished_urls.contains(&entry.url))
+        .collect::<Vec<_>>();
+    let concurrent_strategies = url_concurrency > 1;
 
-            let test_finished = batch_tests(&url, token, number_of_runs, &mut csv_printer).await;
+    futures::stream::iter(entries.into_iter().map(|entry| async move {
+        println!("Testing {url}", url = entry.url);
 
-            if !test_finished {
-                continue;
-            }
+        batch_tests(
+            entry,
+            tester,
+            sampling,
+            confidence_level,
+            concurrent_strategies,
+        )
+        .await
+    }))
+    .buffered(url_concurrency)
+    .for_each(|page| {
+        progress.record(page, tester.replaying());
+        futures::future::ready(())
+    })
+    .await;
 
-            failed_urls.remove(idx);
-        }
+    // Retest the failed runs until failed_pages list is empty
+    for qtt in 0..2 {
+        let failed_pages = std::mem::take(&mut progress.failed_pages);
+
+        futures::stream::iter(failed_pages.into_iter().map(|page| async move {
+            println!(
+                "Retesting {url} {qtt}x",
+                url = page.row.entry.url,
+                qtt = qtt
+            );
+
+            batch_retests(
+                page,
+                tester,
+                qtt + 1,
+                confidence_level,
+                concurrent_strategies,
+            )
+            .await
+        }))
+        .buffered(url_concurrency)
+        .for_each(|page| {
+            progress.record(page, false);
+            futures::future::ready(())
+        })
+        .await;
     }
 
-    for url in failed_urls {
-        println!("Test failed for {url} after two retries", url = url);
+    // Failed urls are reported with their success runs and failure reason
+    for page in std::mem::take(&mut progress.failed_pages) {
+        println!(
+            "Test failed for {url} after two retries",
+            url = page.row.entry.url
+        );
+
+        progress.finish_row(&page.row);
     }
 
-    let _x = csv_printer.flush();
+    progress.batch_printer.finish();
+    progress.checkpoint.remove();
+
+    // Budgets passed
+    progress.budgets_passed
+}
 
-    true
+struct OutlierReport {
+    method: outliers::OutlierMethod,
+    /// Run indices considered outliers for each metric.
+    runs: PSIStatisticResult<Vec<usize>>,
+    /// Statistics calculated without the outliers of each metric.
+    statistics: statistics::PageStatistics,
 }
 
 struct TestResult {
-    page_mean: PSIStatisticResult<f64>,
-    page_deviation: PSIStatisticResult<f64>,
-    page_confidence_interval: PSIStatisticResult<(f64, f64)>,
+    statistics: statistics::PageStatistics,
+    confidence_level: f64,
     success_runs: i8,
+    failed_runs: Vec<(usize, RunError)>,
+    adaptive_report: Option<sampling::AdaptiveReport>,
+    outliers: Option<OutlierReport>,
+    budgets: Vec<budget::BudgetResult>,
+    values: PSIResultValues,
+    baseline: Option<baseline::BaselineCheck>,
+    field_data: field_data::FieldData,
+    opportunities: opportunities::OpportunityReport,
+    third_parties: Vec<third_parties::ThirdPartySummary>,
+    resources: resources::ResourceReport,
 }
 async fn run_single_tests(
     page_url: &str,
-    token: &str,
-    number_of_runs: i8,
+    tester: &tester::Tester,
+    sampling: &sampling::Sampling,
     strategy: Strategy,
+    confidence_level: f64,
+    outlier_method: Option<outliers::OutlierMethod>,
+    budgets: &[budget::Budget],
 ) -> TestResult {
-    let page_result = &tester::get_page_audits(page_url, token, number_of_runs, strategy)
-        .await
-        .unwrap();
+    let (page_audits, adaptive_report) =
+        sampling::sample_page(tester, page_url, sampling, strategy, None).await;
+    let page_result = page_audits.values();
 
-    let _nruns = page_result.score.len() as i8;
+    let _nruns = page_audits.success_runs() as i8;
 
-    let page_mean = statistics::calculate_mean(page_result, _nruns);
+    let outliers = outlier_method.map(|method| {
+        let positions = outliers::calculate_outliers(&page_result, method);
+        let run_indices = page_audits.run_indices();
 
-    let page_deviation = statistics::calculate_deviation(page_result, &page_mean, _nruns);
+        OutlierReport {
+            method,
+            runs: positions.map_metrics(|name, positions| {
+                let run_indices = run_indices.metric(name).unwrap();
 
-    let page_confidence_interval =
-        statistics::calculate_confidence_interval(&page_mean, &page_deviation, _nruns);
+                positions
+                    .iter()
+                    .map(|position| run_indices[*position])
+                    .collect()
+            }),
+            statistics: statistics::calculate_statistics(
+                &outliers::without_outliers(&page_result, &positions),
+                confidence_level,
+            ),
+        }
+    });
+
+    let statistics = statistics::calculate_statistics(&page_result, confidence_level);
+    // Replayed runs only have the origin field data
+    let field_data = match tester.replaying() {
+        true => None,
+        false => tester.get_field_data(page_url, strategy).await,
+    };
 
     TestResult {
-        page_mean,
-        page_deviation,
-        page_confidence_interval,
+        budgets: budget::evaluate(budgets, &statistics),
+        statistics,
+        confidence_level,
         success_runs: _nruns,
+        failed_runs: page_audits
+            .failures()
+            .into_iter()
+            .map(|(index, error)| (index, error.clone()))
+            .collect(),
+        adaptive_report,
+        outliers,
+        values: page_result,
+        baseline: None,
+        field_data: field_data.unwrap_or_else(|| page_audits.field_data()),
+        opportunities: opportunities::aggregate(&page_audits.results()),
+        third_parties: third_parties::aggregate(&page_audits.results()),
+        resources: resources::aggregate(&page_audits.results()),
     }
 }
 
-async fn psi_test() -> Result<(), Error> {
+struct CompareSample {
+    source: String,
+    values: PSIResultValues,
+    failed_runs: Vec<(usize, RunError)>,
+}
+
+/// Tests the page when `source` is an URL, otherwise loads the raw values from the file.
+async fn load_compare_sample(
+    source: &str,
+    tester: &tester::Tester,
+    sampling: &sampling::Sampling,
+    strategy: Strategy,
+) -> CompareSample {
+    if !compare::is_page_url(source) {
+        return CompareSample {
+            source: source.to_string(),
+            values: compare::load_samples(source),
+            failed_runs: Vec::new(),
+        };
+    }
+
+    let (page_audits, _) = sampling::sample_page(tester, source, sampling, strategy, None).await;
+
+    CompareSample {
+        source: source.to_string(),
+        values: page_audits.values(),
+        failed_runs: page_audits
+            .failures()
+            .into_iter()
+            .map(|(index, error)| (index, error.clone()))
+            .collect(),
+    }
+}
+
+/// Returns whether every budget passed and no metric regressed against the baseline.
+async fn psi_test() -> Result<bool, Error> {
     let matches = Command::new("psi-tests")
         .about("PSI Tests is a tool to run multiple page speed insight tests.")
         .long_about(
@@ -229,20 +728,24 @@ async fn psi_test() -> Result<(), Error> {
         psi-test --token=<TOKEN_VALUE> --number-of-runs=10 https://www.google.com
 
         Example: run 5 tests for multiples urls
-        psi-test --token=<TOKEN_VALUE> --number_of_runs=5 -B ./input.txt",
+        psi-test --token=<TOKEN_VALUE> --number_of_runs=5 -B ./input.txt
+
+        Example: compare a production page against a preview deploy
+        psi-test compare --token=<TOKEN_VALUE> https://www.example.com https://preview.example.com",
         )
         // Change if crate_version start work again
         .version(env!("CARGO_PKG_VERSION"))
         .arg(
             Arg::new("token")
+            .global(true)
             .value_name("TOKEN_VALUE")
-            .required(true)
             .short('T')
             .long("token")
             .help("Google cloud token to access Page Speed Insights API. For more informartion: https://developers.google.com/speed/docs/insights/v5/get-started#APIKey"),
         )
         .arg(
             Arg::new("number-of-runs")
+            .global(true)
             .value_name("NUMBER")
             .short('N')
             .long("number-of-runs")
@@ -258,11 +761,12 @@ async fn psi_test() -> Result<(), Error> {
             .value_name("INPUT")
             .short('B')
             .long("batch-file")
-            .help("Batch file allow pass a TXT input file with URLs, line by line, to be tested.")
+            .help("Batch file of the pages to be tested: a plain list of URLs, line by line, or, by extension, a .csv, .json or .yaml/.yml list of entries with a url and an optional label, group, strategies (mobile, desktop), runs and budgets (budget expressions, `;` separated in CSV).")
         )
         .arg(
             // https://developers.google.com/speed/docs/insights/v5/reference/pagespeedapi/runpagespeed#response
             Arg::new("strategy")
+            .global(true)
             .value_name("STRATEGY")
             .short('S')
             .long("strategy")
@@ -277,31 +781,340 @@ This value isn't used when batch_tests flag is present."
         )
         .arg(
             Arg::new("output-format")
+            .global(true)
             .value_name("OUTPUT_FORMAT")
             .short('F')
             .long("output-format")
             .help("output-format can be: md for markdown, json for json. --output-format: md|json.")
         )
+        .arg(
+            Arg::new("output")
+            .global(true)
+            .value_name("PATH")
+            .short('o')
+            .long("output")
+            .help("Writes the report to a file instead of stdout. Batch output goes to ./output.csv, or ./output.json, by default.")
+        )
+        .arg(
+            Arg::new("overwrite")
+            .global(true)
+            .long("overwrite")
+            .conflicts_with("append")
+            .help("Overwrites the output file when it exists. By default a new file is created next to it, e.g. output (1).csv.")
+        )
+        .arg(
+            Arg::new("append")
+            .global(true)
+            .long("append")
+            .help("Appends to the output file when it exists. Batch CSV rows follow the header of the existing file, batch JSON rows extend its array.")
+        )
+        .arg(
+            Arg::new("api-base-url")
+            .global(true)
+            .value_name("URL")
+            .long("api-base-url")
+            .default_value(tester::PSI_API_BASE_URL)
+            .help("Base URL of the Page Speed Insights runPagespeed endpoint. Useful to point the tool to a local mock server.")
+        )
+        .arg(
+            Arg::new("max-attempts")
+            .global(true)
+            .value_name("NUMBER")
+            .long("max-attempts")
+            .default_value("4")
+            .help("Maximum attempts for each PSI run. Throttled (429), server error (5xx) and network failures are retried with exponential backoff, honouring the Retry-After header.")
+        )
+        .arg(
+            Arg::new("qps")
+            .global(true)
+            .value_name("NUMBER")
+            .long("qps")
+            .help("Maximum PSI requests per second, shared by every tested page. Default is 4, PSI default quota of 240 queries per minute.")
+        )
+        .arg(
+            Arg::new("max-concurrency")
+            .global(true)
+            .value_name("NUMBER")
+            .long("max-concurrency")
+            .help("Maximum PSI requests in flight at the same time, shared by every tested page. Default is 15.")
+        )
+        .arg(
+            Arg::new("confidence")
+            .global(true)
+            .value_name("LEVEL")
+            .long("confidence")
+            .help("Confidence level of the Student's t confidence intervals, e.g. 0.90, 0.95 or 0.99. Default is 0.95.")
+        )
+        .arg(
+            Arg::new("outliers")
+            .global(true)
+            .value_name("METHOD")
+            .long("outliers")
+            .possible_values(["iqr", "mad", "trim"])
+            .help("Flags outlier runs of each metric and reports statistics with and without them. iqr: outside Tukey's fences (1.5 IQR), mad: modified z-score above 3.5, trim: lowest and highest --trim-fraction of the runs.")
+        )
+        .arg(
+            Arg::new("trim-fraction")
+            .global(true)
+            .value_name("FRACTION")
+            .long("trim-fraction")
+            .default_value("0.1")
+            .help("Fraction of runs trimmed from each end by --outliers=trim.")
+        )
+        .arg(
+            Arg::new("target-ci")
+            .global(true)
+            .value_name("HALF_WIDTH")
+            .long("target-ci")
+            .help("Enables adaptive sampling: runs in waves until the confidence interval half-width of --ci-metric is below HALF_WIDTH, bounded by --min-runs and --max-runs. --number-of-runs is ignored.")
+        )
+        .arg(
+            Arg::new("categories")
+            .global(true)
+            .value_name("CATEGORIES")
+            .long("categories")
+            .use_value_delimiter(true)
+            .possible_values(metrics::CATEGORIES)
+            .help("Comma separated Lighthouse categories to test, e.g. performance,accessibility,seo. The score of every category is reported, performance is always tested.")
+        )
+        .arg(
+            Arg::new("metric")
+            .global(true)
+            .value_name("AUDIT_ID")
+            .long("metric")
+            .multiple_occurrences(true)
+            .help("Also reports the numericValue of a Lighthouse audit, e.g. network-rtt or network-server-latency. Can be used multiple times.")
+        )
+        .arg(
+            Arg::new("ci-metric")
+            .global(true)
+            .value_name("METRIC")
+            .long("ci-metric")
+            .default_value("score")
+            .help("Output name of the metric whose confidence interval is checked by adaptive sampling, e.g. score or largest_contentful_paint.")
+        )
+        .arg(
+            Arg::new("min-runs")
+            .global(true)
+            .value_name("NUMBER")
+            .long("min-runs")
+            .help("Runs of the first adaptive sampling wave. Default is 5.")
+        )
+        .arg(
+            Arg::new("max-runs")
+            .global(true)
+            .value_name("NUMBER")
+            .long("max-runs")
+            .help("Maximum runs of adaptive sampling. Default is 50.")
+        )
+        .arg(
+            Arg::new("alpha")
+            .global(true)
+            .value_name("ALPHA")
+            .long("alpha")
+            .help("Significance level of compare p-values and --against-baseline regressions. Default is 0.05.")
+        )
+        .arg(
+            Arg::new("raw-output")
+            .global(true)
+            .value_name("FILE")
+            .long("raw-output")
+            .help("Writes every run, one JSON object per line, with its URL, strategy, run index, timestamps, Lighthouse version, final URL, metrics and error.")
+        )
+        .arg(
+            Arg::new("save-responses")
+            .global(true)
+            .value_name("DIR")
+            .long("save-responses")
+            .help("Saves the raw JSON of every PSI response to a directory, to be replayed later with --replay.")
+        )
+        .arg(
+            Arg::new("replay")
+            .global(true)
+            .value_name("DIR")
+            .long("replay")
+            .conflicts_with("save-responses")
+            .help("Replays the PSI responses saved with --save-responses instead of calling PSI. Every saved run of each page and strategy is used, whatever the number of runs.")
+        )
+        .arg(
+            Arg::new("checkpoint")
+            .value_name("FILE")
+            .long("checkpoint")
+            .help("Checkpoint file of the batch, recording the raw results of every finished URL. Default is the batch file name followed by .checkpoint.jsonl, it's removed once the batch finishes.")
+        )
+        .arg(
+            Arg::new("resume")
+            .long("resume")
+            .help("Resumes an interrupted batch from its checkpoint file: finished URLs are reported from it without testing them again.")
+        )
+        .arg(
+            Arg::new("url-concurrency")
+            .value_name("NUMBER")
+            .long("url-concurrency")
+            .help("Number of batch URLs tested at the same time, with both strategies of each URL tested concurrently when above 1. Requests still respect --qps and --max-concurrency. Default is 1.")
+        )
+        .arg(
+            Arg::new("save-baseline")
+            .value_name("FILE")
+            .long("save-baseline")
+            .help("Saves the raw values of every run, and their statistics, to a baseline JSON file.")
+        )
+        .arg(
+            Arg::new("against-baseline")
+            .value_name("FILE")
+            .long("against-baseline")
+            .help("Compares the runs with a baseline saved by --save-baseline and flags the metrics that regressed, Mann-Whitney U p-value below --alpha with the Holm correction across metrics. The process exits with a non-zero code on regression.")
+        )
+        .arg(
+            Arg::new("budget")
+            .value_name("FILE")
+            .long("budget")
+            .help("TOML or JSON file with a `budgets` list, e.g. [\"score.median >= 0.8\", \"largest_contentful_paint.p75 <= 2500\"]. The process exits with a non-zero code when a budget fails.")
+        )
+        .subcommand(
+            Command::new("compare")
+            .about("Compares two pages, reporting per metric deltas, Welch's t-test and Mann-Whitney U p-values and effect sizes.")
+            .arg(
+                Arg::new("page-a")
+                .value_name("A")
+                .required(true)
+                .index(1)
+                .help("Reference page URL, or a JSON file with the raw values of each metric.")
+            )
+            .arg(
+                Arg::new("page-b")
+                .value_name("B")
+                .required(true)
+                .index(2)
+                .help("Candidate page URL, or a JSON file with the raw values of each metric.")
+            )
+        )
         .get_matches();
 
-    // Required value
-    let token = matches.value_of("token").expect("Token is required!");
+    // Shared options are global, so they are read from the subcommand matches when it's used
+    let (args, subcommand) = match matches.subcommand() {
+        Some((name, sub_matches)) => (sub_matches, Some(name)),
+        None => (&matches, None),
+    };
+    let matches = args;
+
     let number_of_runs = match matches.value_of("number-of-runs") {
         Some(value) => value.parse::<i8>().unwrap(),
         None => SAMPLE,
     };
+    let api_base_url = matches
+        .value_of("api-base-url")
+        .expect("API base URL has a default value");
+    let max_attempts = matches
+        .value_of("max-attempts")
+        .expect("Max attempts has a default value")
+        .parse::<u32>()
+        .unwrap()
+        .max(1);
+    let qps = match matches.value_of("qps") {
+        Some(value) => value
+            .parse::<f64>()
+            .ok()
+            .filter(|qps| *qps > 0_f64)
+            .expect("QPS must be a number greater than zero"),
+        None => tester::DEFAULT_QPS,
+    };
+    let max_concurrency = match matches.value_of("max-concurrency") {
+        Some(value) => value.parse::<usize>().unwrap().max(1),
+        None => tester::DEFAULT_MAX_CONCURRENCY,
+    };
 
-    // Run batch tests
-    if let Some(batch) = matches.value_of("batch") {
-        run_batch_tests(batch, token, number_of_runs).await;
+    let mut categories = matches
+        .values_of("categories")
+        .map(Iterator::collect::<Vec<_>>)
+        .unwrap_or_default();
+    if !categories.contains(&metrics::PERFORMANCE_CATEGORY) {
+        categories.insert(0, metrics::PERFORMANCE_CATEGORY);
+    }
 
-        return Ok(());
+    let metrics = metrics::registry(
+        &categories,
+        &matches
+            .values_of("metric")
+            .map(Iterator::collect::<Vec<_>>)
+            .unwrap_or_default(),
+    );
+
+    // Token is only required when some page is tested
+    let mut tester = tester::Tester::new(
+        matches.value_of("token").unwrap_or_default(),
+        api_base_url,
+        tester::RetryPolicy::new(max_attempts),
+        rate_limiter::RateLimiter::new(qps),
+        max_concurrency,
+        &categories,
+        metrics.clone(),
+    )?;
+    if let Some(path) = matches.value_of("raw-output") {
+        tester = tester.with_raw_output(raw_output::RawOutput::create(path));
+    }
+    if let Some(dir) = matches.value_of("save-responses") {
+        tester = tester.with_save_responses(dir);
     }
+    if let Some(dir) = matches.value_of("replay") {
+        tester = tester.with_replay(dir);
+    }
+    // Replayed responses don't need any request to PSI
+    let require_token = || {
+        if !matches.is_present("replay") {
+            matches.value_of("token").expect("Token is required!");
+        }
+    };
 
-    // Required value
-    let page_url = matches
-        .value_of("first-page")
-        .expect("Page URL is required");
+    let confidence_level = match matches.value_of("confidence") {
+        Some(value) => value
+            .parse::<f64>()
+            .ok()
+            .filter(|level| *level > 0_f64 && *level < 1_f64)
+            .expect("Confidence level must be a number between 0 and 1"),
+        None => statistics::DEFAULT_CONFIDENCE_LEVEL,
+    };
+
+    let outlier_method = match matches.value_of("outliers") {
+        Some("iqr") => Some(outliers::OutlierMethod::Iqr),
+        Some("mad") => Some(outliers::OutlierMethod::Mad),
+        Some("trim") => Some(outliers::OutlierMethod::Trim(
+            matches
+                .value_of("trim-fraction")
+                .expect("Trim fraction has a default value")
+                .parse::<f64>()
+                .ok()
+                .filter(|fraction| *fraction >= 0_f64 && *fraction < 0.5)
+                .expect("Trim fraction must be a number between 0 and 0.5"),
+        )),
+        _ => None,
+    };
+
+    let sampling = match matches.value_of("target-ci") {
+        Some(value) => {
+            let min_runs = match matches.value_of("min-runs") {
+                Some(value) => value.parse::<i8>().unwrap().max(2),
+                None => ADAPTIVE_MIN_RUNS,
+            };
+            let max_runs = match matches.value_of("max-runs") {
+                Some(value) => value.parse::<i8>().unwrap(),
+                None => ADAPTIVE_MAX_RUNS,
+            };
+
+            sampling::Sampling::Adaptive(sampling::AdaptiveSampling {
+                metric: matches
+                    .value_of("ci-metric")
+                    .filter(|name| metrics.iter().any(|metric| metric.name == *name))
+                    .expect("CI metric must be the output name of a reported metric")
+                    .to_string(),
+                target_half_width: value.parse::<f64>().unwrap(),
+                confidence_level,
+                min_runs,
+                max_runs: max_runs.max(min_runs),
+            })
+        }
+        None => sampling::Sampling::Fixed(number_of_runs),
+    };
 
     let strategy = match matches.value_of("strategy") {
         Some(value) => {
@@ -315,33 +1128,155 @@ This value isn't used when batch_tests flag is present."
     };
 
     let output_format = matches.value_of("output-format").unwrap_or("json");
+    let output_path = matches.value_of("output");
+    let write_mode = if matches.is_present("overwrite") {
+        output::WriteMode::Overwrite
+    } else if matches.is_present("append") {
+        output::WriteMode::Append
+    } else {
+        output::WriteMode::NewFile
+    };
 
-    let test_result = run_single_tests(page_url, token, number_of_runs, strategy).await;
+    let alpha = match matches.value_of("alpha") {
+        Some(value) => value.parse::<f64>().unwrap(),
+        None => compare::DEFAULT_ALPHA,
+    };
 
-    if output_format == "md" {
-        printer::print_md(
-            page_url,
-            test_result.success_runs,
-            &test_result.page_mean,
-            &test_result.page_deviation,
-            &test_result.page_confidence_interval,
+    if subcommand == Some("compare") {
+        let source_a = matches.value_of("page-a").expect("A is required");
+        let source_b = matches.value_of("page-b").expect("B is required");
+
+        if compare::is_page_url(source_a) || compare::is_page_url(source_b) {
+            require_token();
+        }
+
+        let (sample_a, sample_b) = futures::join!(
+            load_compare_sample(source_a, &tester, &sampling, strategy),
+            load_compare_sample(source_b, &tester, &sampling, strategy),
         );
-    } else if output_format == "json" {
-        printer::print_json(
-            page_url,
-            test_result.success_runs,
-            &test_result.page_mean,
-            &test_result.page_deviation,
-            &test_result.page_confidence_interval,
+        let comparison = compare::compare(&sample_a.values, &sample_b.values);
+
+        let mut report = String::new();
+        if output_format == "md" {
+            printer::print_compare_md(&mut report, &sample_a, &sample_b, &comparison, alpha);
+        } else if output_format == "json" {
+            printer::print_compare_json(&mut report, &sample_a, &sample_b, &comparison, alpha);
+        }
+        output::write_report(output_path, write_mode, &report);
+
+        return Ok(true);
+    }
+
+    require_token();
+
+    let budgets = match matches.value_of("budget") {
+        Some(path) => budget::load_budgets(path, &metrics),
+        None => Vec::new(),
+    };
+
+    // Run batch tests
+    if let Some(batch) = matches.value_of("batch") {
+        let checkpoint_path = match matches.value_of("checkpoint") {
+            Some(path) => path.to_string(),
+            None => format!("{batch}.checkpoint.jsonl", batch = batch),
+        };
+        let url_concurrency = match matches.value_of("url-concurrency") {
+            Some(value) => value.parse::<usize>().unwrap().max(1),
+            None => DEFAULT_URL_CONCURRENCY,
+        };
+        // Loaded before testing, an invalid entry must not waste quota
+        let entries = batch_input::load_entries(batch, &metrics);
+        let json = matches.value_of("output-format") == Some("json");
+        let resume = matches.is_present("resume");
+        let mut batch_printer = printer::BatchPrinter::new(json, &metrics, output_path, write_mode);
+        let checkpoint =
+            checkpoint::Checkpoint::open(&checkpoint_path, resume, confidence_level, &metrics);
+        // CSV rows are flushed as soon as they're written, appending to the output of the
+        // interrupted batch they're already there. JSON is only written once the batch finishes.
+        let resumed_rows_written = resume && write_mode == output::WriteMode::Append && !json;
+        let budgets_passed = run_batch_tests(
+            &entries,
+            &tester,
+            &sampling,
+            confidence_level,
+            url_concurrency,
+            BatchProgress::new(
+                &mut batch_printer,
+                checkpoint,
+                &entries,
+                &budgets,
+                resumed_rows_written,
+            ),
         )
+        .await;
+
+        return Ok(budgets_passed);
+    }
+
+    // Required value
+    let page_url = matches
+        .value_of("first-page")
+        .expect("Page URL is required");
+
+    // Loaded before testing, an invalid baseline must not waste quota
+    let against_baseline = matches.value_of("against-baseline").map(|path| {
+        let baseline = baseline::load(path);
+        baseline::check_page(&baseline, page_url, &strategy.to_string()).unwrap();
+
+        (path, baseline)
+    });
+
+    let mut test_result = run_single_tests(
+        page_url,
+        &tester,
+        &sampling,
+        strategy,
+        confidence_level,
+        outlier_method,
+        &budgets,
+    )
+    .await;
+
+    if let Some((path, baseline)) = &against_baseline {
+        test_result.baseline = Some(baseline::check(path, baseline, &test_result.values, alpha));
+    }
+
+    if let Some(path) = matches.value_of("save-baseline") {
+        baseline::save(
+            path,
+            &baseline::Baseline {
+                url: page_url.to_string(),
+                strategy: strategy.to_string(),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                values: test_result.values.clone(),
+                statistics: printer::statistics_json(&test_result.statistics),
+            },
+        );
+    }
+
+    let mut report = String::new();
+    if output_format == "md" {
+        printer::print_md(&mut report, page_url, &test_result);
+    } else if output_format == "json" {
+        printer::print_json(&mut report, page_url, &test_result)
     }
+    output::write_report(output_path, write_mode, &report);
+
+    let no_regressions = test_result
+        .baseline
+        .as_ref()
+        .is_none_or(|check| check.regressions.is_empty());
 
-    Ok(())
+    Ok(budget::all_passed(&test_result.budgets) && no_regressions)
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Error> {
-    psi_test().await?;
+async fn main() -> Result<ExitCode, Error> {
+    let passed = psi_test().await?;
+
+    if !passed {
+        return Ok(ExitCode::FAILURE);
+    }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,329 @@
+use crate::LHResult;
+
+pub const PERFORMANCE_CATEGORY: &str = "performance";
+/// Lighthouse categories, the performance one is always tested.
+pub const CATEGORIES: [&str; 5] = [
+    PERFORMANCE_CATEGORY,
+    "accessibility",
+    "best-practices",
+    "seo",
+    "pwa",
+];
+
+/// Where the value of a metric is in a Lighthouse result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricSource {
+    /// `numericValue` of an audit, by audit id.
+    Audit(String),
+    /// Score of a category, by category id.
+    CategoryScore(String),
+}
+
+/// A metric extracted from every run, with how it's reported.
+#[derive(Debug, Clone)]
+pub struct Metric {
+    /// Output name, e.g. largest_contentful_paint.
+    pub name: String,
+    pub source: MetricSource,
+    pub display_name: String,
+    /// Unit of the value, e.g. ms. Empty for unitless metrics, as CLS and the score.
+    pub unit: String,
+    pub decimals: usize,
+    pub lower_is_better: bool,
+}
+
+impl Metric {
+    fn new(
+        name: &str,
+        source: MetricSource,
+        display_name: &str,
+        unit: &str,
+        decimals: usize,
+        lower_is_better: bool,
+    ) -> Metric {
+        Metric {
+            name: name.to_string(),
+            source,
+            display_name: display_name.to_string(),
+            unit: unit.to_string(),
+            decimals,
+            lower_is_better,
+        }
+    }
+
+    /// Any Lighthouse audit picked with `--metric`. The audit id is also its output name.
+    pub fn custom(audit_id: &str) -> Metric {
+        Metric::new(
+            audit_id,
+            MetricSource::Audit(audit_id.to_string()),
+            audit_id,
+            "",
+            2,
+            true,
+        )
+    }
+
+    fn audit(
+        name: &str,
+        audit_id: &str,
+        display_name: &str,
+        unit: &str,
+        decimals: usize,
+    ) -> Metric {
+        Metric::new(
+            name,
+            MetricSource::Audit(audit_id.to_string()),
+            display_name,
+            unit,
+            decimals,
+            true,
+        )
+    }
+
+    /// Score of a Lighthouse category, from 0 to 1. The performance one keeps its historical
+    /// name, score.
+    pub fn category_score(category_id: &str) -> Metric {
+        let (name, display_name) = match category_id {
+            PERFORMANCE_CATEGORY => ("score".to_string(), "Performance score".to_string()),
+            "best-practices" => (
+                "best_practices_score".to_string(),
+                "Best Practices score".to_string(),
+            ),
+            "seo" => ("seo_score".to_string(), "SEO score".to_string()),
+            "pwa" => ("pwa_score".to_string(), "PWA score".to_string()),
+            _ => (
+                format!("{}_score", category_id.replace('-', "_")),
+                format!(
+                    "{}{} score",
+                    category_id[..1].to_uppercase(),
+                    &category_id[1..]
+                ),
+            ),
+        };
+
+        Metric::new(
+            &name,
+            MetricSource::CategoryScore(category_id.to_string()),
+            &display_name,
+            "",
+            3,
+            false,
+        )
+    }
+
+    /// Decimals of the standard deviation, variance and confidence interval. Unitless metrics
+    /// are small fractions, their spread needs more precision than their mean.
+    pub fn spread_decimals(&self) -> usize {
+        if self.unit.is_empty() {
+            self.decimals * 2
+        } else {
+            self.decimals
+        }
+    }
+
+    /// Value of the metric in a run, `None` when the run doesn't have the audit or category, or
+    /// it has no value.
+    pub fn value(&self, result: &LHResult) -> Option<f64> {
+        match &self.source {
+            MetricSource::Audit(audit_id) => result
+                .audits
+                .get(audit_id)
+                .and_then(|audit| audit.numeric_value),
+            MetricSource::CategoryScore(category_id) => result
+                .categories
+                .get(category_id)
+                .and_then(|category| category.score),
+        }
+    }
+}
+
+/// Metrics always reported, in the order of the output tables.
+pub fn builtin_metrics() -> Vec<Metric> {
+    vec![
+        Metric::audit(
+            "cumulative_layout_shift",
+            "cumulative-layout-shift",
+            "Cumulative Layout shift (CLS)",
+            "",
+            2,
+        ),
+        Metric::audit(
+            "first_contentful_paint",
+            "first-contentful-paint",
+            "First Contentful Paint (FCP)",
+            "ms",
+            2,
+        ),
+        Metric::audit(
+            "largest_contentful_paint",
+            "largest-contentful-paint",
+            "Largest Contentful Paint (LCP)",
+            "ms",
+            2,
+        ),
+        Metric::audit(
+            "time_to_interactive",
+            "interactive",
+            "Time to Interactive (TTI)",
+            "ms",
+            2,
+        ),
+        Metric::audit(
+            "total_blocking_time",
+            "total-blocking-time",
+            "Total Blocking Time (TBT)",
+            "ms",
+            2,
+        ),
+        Metric::category_score(PERFORMANCE_CATEGORY),
+        Metric::audit(
+            "js_execution_time",
+            "bootup-time",
+            "JavaScript Execution Time",
+            "ms",
+            2,
+        ),
+        Metric::audit("speed_index", "speed-index", "Speed Index", "ms", 2),
+        Metric::audit(
+            "server_response_time",
+            "server-response-time",
+            "Server Response Time (TTFB)",
+            "ms",
+            2,
+        ),
+        Metric::audit(
+            "max_potential_fid",
+            "max-potential-fid",
+            "Max Potential First Input Delay",
+            "ms",
+            2,
+        ),
+        Metric::audit("dom_size", "dom-size", "DOM Size", "elements", 0),
+        Metric::audit(
+            "total_byte_weight",
+            "total-byte-weight",
+            "Total Byte Weight",
+            "bytes",
+            0,
+        ),
+        Metric::audit(
+            "main_thread_work",
+            "mainthread-work-breakdown",
+            "Main-Thread Work",
+            "ms",
+            2,
+        ),
+        Metric::audit(
+            "first_meaningful_paint",
+            "first-meaningful-paint",
+            "First Meaningful Paint (FMP)",
+            "ms",
+            2,
+        ),
+    ]
+}
+
+/// Builtin metrics and the score of every other known category, in the order of the output
+/// tables.
+fn known_metrics() -> Vec<Metric> {
+    let mut metrics = builtin_metrics();
+    metrics.extend(
+        CATEGORIES
+            .iter()
+            .filter(|category_id| **category_id != PERFORMANCE_CATEGORY)
+            .map(|category_id| Metric::category_score(category_id)),
+    );
+
+    metrics
+}
+
+/// Builtin metrics, the score of the other tested categories, then the custom audits, skipping
+/// the ones already reported.
+pub fn registry(categories: &[&str], custom_audit_ids: &[&str]) -> Vec<Metric> {
+    let mut metrics = builtin_metrics();
+
+    for category_id in categories {
+        let metric = Metric::category_score(category_id);
+
+        if !metrics.iter().any(|known| known.source == metric.source) {
+            metrics.push(metric);
+        }
+    }
+
+    for audit_id in custom_audit_ids {
+        let known = metrics.iter().any(|metric| {
+            metric.name == *audit_id || metric.source == MetricSource::Audit(audit_id.to_string())
+        });
+
+        if !known {
+            metrics.push(Metric::custom(audit_id));
+        }
+    }
+
+    metrics
+}
+
+/// Registry entry of a metric by its output name. Unknown names are custom audits.
+pub fn find(name: &str) -> Metric {
+    known_metrics()
+        .into_iter()
+        .find(|metric| metric.name == name)
+        .unwrap_or_else(|| Metric::custom(name))
+}
+
+/// Position of a metric in the output tables, custom audits go after the known metrics.
+pub fn position(name: &str) -> usize {
+    known_metrics()
+        .iter()
+        .position(|metric| metric.name == name)
+        .unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    #[test]
+    fn registry() {
+        let metrics = super::registry(
+            &["performance", "seo", "accessibility", "seo"],
+            &[
+                "uses-long-cache-ttl",
+                "server-response-time",
+                "interactive",
+                "score",
+            ],
+        );
+        let names = metrics
+            .iter()
+            .map(|metric| metric.name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(17, metrics.len());
+        assert_eq!("server_response_time", names[8]);
+        assert_eq!(
+            vec!["seo_score", "accessibility_score", "uses-long-cache-ttl"],
+            names[14..]
+        );
+        assert_eq!("Speed Index", super::find("speed_index").display_name);
+        assert_eq!(
+            "Best Practices score",
+            super::find("best_practices_score").display_name
+        );
+        assert_eq!(
+            super::MetricSource::Audit("dom-size".to_string()),
+            super::find("dom-size").source
+        );
+    }
+
+    #[test]
+    fn value() {
+        let result = crate::tester::parse_response(include_str!("../res.json"))
+            .unwrap()
+            .lighthouse_result;
+
+        assert_eq!(Some(0.93), super::find("score").value(&result));
+        assert!(super::find("largest_contentful_paint")
+            .value(&result)
+            .is_some());
+        assert_eq!(None, super::find("not-an-audit").value(&result));
+    }
+}
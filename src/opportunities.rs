@@ -0,0 +1,283 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::metrics::PERFORMANCE_CATEGORY;
+use crate::statistics::percentiles;
+use crate::{Audit, LHResult};
+
+/// Lighthouse shows an audit as passed from this score on.
+const PASS_THRESHOLD: f64 = 0.9_f64;
+/// Offending URLs reported for each audit.
+const TOP_URLS: usize = 5;
+/// Performance audit groups that aren't opportunities or diagnostics.
+const SKIPPED_GROUPS: [&str; 3] = ["metrics", "hidden", "budgets"];
+
+#[derive(Debug, Serialize)]
+pub struct OffendingUrl {
+    pub url: String,
+    /// Runs in which the URL was listed by the audit.
+    pub runs: usize,
+    pub median_wasted_ms: Option<f64>,
+    pub median_wasted_bytes: Option<f64>,
+}
+
+/// An opportunity or diagnostic aggregated across runs.
+#[derive(Debug, Serialize)]
+pub struct AuditSummary {
+    pub id: String,
+    pub title: String,
+    /// Runs in which the audit didn't pass.
+    pub runs_fired: usize,
+    pub runs: usize,
+    pub median_score: Option<f64>,
+    /// Medians of the runs in which the audit fired.
+    pub median_savings_ms: Option<f64>,
+    pub median_savings_bytes: Option<f64>,
+    pub top_urls: Vec<OffendingUrl>,
+}
+
+/// What to fix: opportunities ranked by estimated savings, diagnostics by how often they fired.
+#[derive(Debug, Serialize)]
+pub struct OpportunityReport {
+    pub opportunities: Vec<AuditSummary>,
+    pub diagnostics: Vec<AuditSummary>,
+}
+
+fn median(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    Some(percentiles(values).median)
+}
+
+/// Opportunities have an estimated saving, diagnostics are every other scored audit of the
+/// performance category. Both fire when their score is below the pass threshold.
+fn is_fired(audit: &Audit) -> bool {
+    let applicable = !matches!(
+        audit.score_display_mode.as_deref(),
+        Some("notApplicable" | "manual" | "error" | "informative")
+    );
+
+    applicable && audit.score.is_some_and(|score| score < PASS_THRESHOLD)
+}
+
+fn is_opportunity(audit: &Audit) -> bool {
+    audit
+        .details
+        .as_ref()
+        .and_then(|details| details.kind.as_deref())
+        == Some("opportunity")
+}
+
+/// Audit ids of the opportunities and diagnostics of the performance category.
+fn performance_audit_ids(result: &LHResult) -> Vec<String> {
+    result
+        .categories
+        .get(PERFORMANCE_CATEGORY)
+        .map(|category| {
+            category
+                .audit_refs
+                .iter()
+                .filter(|audit_ref| {
+                    !audit_ref
+                        .group
+                        .as_deref()
+                        .is_some_and(|group| SKIPPED_GROUPS.contains(&group))
+                })
+                .map(|audit_ref| audit_ref.id.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn top_urls(fired_audits: &[&Audit]) -> Vec<OffendingUrl> {
+    // URL -> (runs, wasted ms, wasted bytes)
+    let mut urls: HashMap<&str, (usize, Vec<f64>, Vec<f64>)> = HashMap::new();
+
+    for audit in fired_audits {
+        let items = audit
+            .details
+            .as_ref()
+            .map(|details| details.items.as_slice())
+            .unwrap_or_default();
+        let mut seen = Vec::new();
+
+        for item in items {
+            let url = match item.get("url").and_then(serde_json::Value::as_str) {
+                Some(url) => url,
+                None => continue,
+            };
+            let entry = urls.entry(url).or_default();
+
+            // A URL listed twice in the same run counts once
+            if !seen.contains(&url) {
+                seen.push(url);
+                entry.0 += 1;
+            }
+            if let Some(wasted_ms) = item.get("wastedMs").and_then(serde_json::Value::as_f64) {
+                entry.1.push(wasted_ms);
+            }
+            if let Some(wasted_bytes) = item.get("wastedBytes").and_then(serde_json::Value::as_f64)
+            {
+                entry.2.push(wasted_bytes);
+            }
+        }
+    }
+
+    let mut top_urls = urls
+        .into_iter()
+        .map(|(url, (runs, wasted_ms, wasted_bytes))| OffendingUrl {
+            url: url.to_string(),
+            runs,
+            median_wasted_ms: median(&wasted_ms),
+            median_wasted_bytes: median(&wasted_bytes),
+        })
+        .collect::<Vec<_>>();
+    top_urls.sort_by(|a, b| {
+        b.runs
+            .cmp(&a.runs)
+            .then(
+                b.median_wasted_bytes
+                    .unwrap_or_default()
+                    .total_cmp(&a.median_wasted_bytes.unwrap_or_default()),
+            )
+            .then(a.url.cmp(&b.url))
+    });
+    top_urls.truncate(TOP_URLS);
+
+    top_urls
+}
+
+fn summarize(id: &str, audits: &[&Audit]) -> AuditSummary {
+    let fired_audits = audits
+        .iter()
+        .copied()
+        .filter(|audit| is_fired(audit))
+        .collect::<Vec<_>>();
+    let scores = audits
+        .iter()
+        .filter_map(|audit| audit.score)
+        .collect::<Vec<_>>();
+    let savings = |saving: fn(&Audit) -> Option<f64>| {
+        median(
+            &fired_audits
+                .iter()
+                .filter_map(|audit| saving(audit))
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    AuditSummary {
+        id: id.to_string(),
+        title: audits
+            .iter()
+            .find_map(|audit| audit.title.clone())
+            .unwrap_or_else(|| id.to_string()),
+        runs_fired: fired_audits.len(),
+        runs: audits.len(),
+        median_score: median(&scores),
+        median_savings_ms: savings(|audit| {
+            audit
+                .details
+                .as_ref()
+                .and_then(|details| details.overall_savings_ms)
+        }),
+        median_savings_bytes: savings(|audit| {
+            audit
+                .details
+                .as_ref()
+                .and_then(|details| details.overall_savings_bytes)
+        }),
+        top_urls: top_urls(&fired_audits),
+    }
+}
+
+/// Aggregates the opportunities and diagnostics that fired in at least one run.
+pub fn aggregate(lh_results: &[&LHResult]) -> OpportunityReport {
+    let mut ids = lh_results
+        .iter()
+        .flat_map(|result| performance_audit_ids(result))
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+
+    let (mut opportunities, mut diagnostics): (Vec<_>, Vec<_>) = ids
+        .iter()
+        .filter_map(|id| {
+            let audits = lh_results
+                .iter()
+                .filter_map(|result| result.audits.get(id))
+                .collect::<Vec<_>>();
+            let summary = summarize(id, &audits);
+
+            if summary.runs_fired == 0 {
+                return None;
+            }
+
+            Some((audits.iter().any(|audit| is_opportunity(audit)), summary))
+        })
+        .partition(|(opportunity, _)| *opportunity);
+
+    opportunities.sort_by(|(_, a), (_, b)| {
+        b.median_savings_ms
+            .unwrap_or_default()
+            .total_cmp(&a.median_savings_ms.unwrap_or_default())
+            .then(
+                b.median_savings_bytes
+                    .unwrap_or_default()
+                    .total_cmp(&a.median_savings_bytes.unwrap_or_default()),
+            )
+            .then(b.runs_fired.cmp(&a.runs_fired))
+    });
+    diagnostics.sort_by(|(_, a), (_, b)| {
+        b.runs_fired.cmp(&a.runs_fired).then(
+            a.median_score
+                .unwrap_or_default()
+                .total_cmp(&b.median_score.unwrap_or_default()),
+        )
+    });
+
+    OpportunityReport {
+        opportunities: opportunities
+            .into_iter()
+            .map(|(_, summary)| summary)
+            .collect(),
+        diagnostics: diagnostics
+            .into_iter()
+            .map(|(_, summary)| summary)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod opportunities_tests {
+    #[test]
+    fn aggregate() {
+        let result = crate::tester::parse_response(include_str!("../res.json"))
+            .unwrap()
+            .lighthouse_result;
+        let report = super::aggregate(&[&result, &result]);
+
+        let unused_javascript = &report.opportunities[0];
+        assert_eq!("unused-javascript", unused_javascript.id);
+        assert_eq!(2, unused_javascript.runs_fired);
+        assert_eq!(Some(150_f64), unused_javascript.median_savings_ms);
+        assert_eq!(1, unused_javascript.top_urls.len());
+        assert_eq!(2, unused_javascript.top_urls[0].runs);
+        assert_eq!(
+            Some(26674_f64),
+            unused_javascript.top_urls[0].median_wasted_bytes
+        );
+
+        // Passed with a score of 1
+        assert!(report
+            .opportunities
+            .iter()
+            .all(|summary| summary.id != "server-response-time"));
+        assert!(report
+            .diagnostics
+            .iter()
+            .any(|summary| summary.id == "mainthread-work-breakdown"));
+    }
+}
@@ -0,0 +1,215 @@
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{LHResult, PSIResult};
+
+/// Reason why a single PSI run didn't produce a lighthouse result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RunError {
+    /// The API answered with a non success status and without an error body.
+    Http { status: u16 },
+    /// The API answered with a non success status and a PSI `error.message` body.
+    Api { status: u16, message: String },
+    /// The API key ran out of quota (HTTP 429 or `RESOURCE_EXHAUSTED`).
+    QuotaExceeded { message: String },
+    /// The response body isn't a valid PSI result.
+    Decode { message: String },
+    /// The request didn't finish before the client timeout.
+    Timeout,
+    /// The request couldn't reach the API.
+    Connection { message: String },
+    /// Lighthouse ran but couldn't audit the page, e.g. NO_FCP.
+    Lighthouse { code: String, message: String },
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::Http { status } => write!(f, "HTTP {status}", status = status),
+            RunError::Api { status, message } => {
+                write!(
+                    f,
+                    "HTTP {status}: {message}",
+                    status = status,
+                    message = message
+                )
+            }
+            RunError::QuotaExceeded { message } => {
+                write!(f, "quota exceeded: {message}", message = message)
+            }
+            RunError::Decode { message } => {
+                write!(f, "invalid PSI response: {message}", message = message)
+            }
+            RunError::Timeout => write!(f, "request timed out"),
+            RunError::Connection { message } => {
+                write!(f, "connection error: {message}", message = message)
+            }
+            RunError::Lighthouse { code, message } => {
+                write!(
+                    f,
+                    "lighthouse error {code}: {message}",
+                    code = code,
+                    message = message
+                )
+            }
+        }
+    }
+}
+
+/// Prefix of the message of the HTTP 500 PSI answers when Lighthouse fails to audit the page,
+/// followed by the Lighthouse error code, e.g. `Lighthouse returned error: NO_FCP. The page…`.
+const LIGHTHOUSE_ERROR_PREFIX: &str = "Lighthouse returned error: ";
+
+#[derive(Deserialize, Debug)]
+struct PSIErrorBody {
+    error: PSIError,
+}
+
+#[derive(Deserialize, Debug)]
+struct PSIError {
+    message: String,
+    status: Option<String>,
+}
+
+/// Lighthouse error code of a PSI error message, when Lighthouse failed to audit the page.
+fn lighthouse_error_code(message: &str) -> Option<String> {
+    let code = message
+        .strip_prefix(LIGHTHOUSE_ERROR_PREFIX)?
+        .chars()
+        .take_while(|character| character.is_ascii_uppercase() || *character == '_')
+        .collect::<String>();
+
+    (!code.is_empty()).then_some(code)
+}
+
+impl RunError {
+    /// Classifies a non success response using its status code and PSI error body.
+    pub fn from_response(status: StatusCode, body: &str) -> RunError {
+        let psi_error = serde_json::from_str::<PSIErrorBody>(body).ok();
+        let is_quota_error = status == StatusCode::TOO_MANY_REQUESTS
+            || psi_error
+                .as_ref()
+                .and_then(|body| body.error.status.as_deref())
+                .is_some_and(|status| status == "RESOURCE_EXHAUSTED");
+
+        match psi_error {
+            Some(body) if is_quota_error => RunError::QuotaExceeded {
+                message: body.error.message,
+            },
+            None if is_quota_error => RunError::QuotaExceeded {
+                message: status.to_string(),
+            },
+            Some(body) => match lighthouse_error_code(&body.error.message) {
+                Some(code) => RunError::Lighthouse {
+                    code,
+                    message: body.error.message,
+                },
+                None => RunError::Api {
+                    status: status.as_u16(),
+                    message: body.error.message,
+                },
+            },
+            None => RunError::Http {
+                status: status.as_u16(),
+            },
+        }
+    }
+}
+
+impl From<reqwest::Error> for RunError {
+    fn from(error: reqwest::Error) -> Self {
+        // The request URL has the API key, so it's left out of the message
+        let error = error.without_url();
+
+        if error.is_timeout() {
+            RunError::Timeout
+        } else if error.is_decode() {
+            RunError::Decode {
+                message: error.to_string(),
+            }
+        } else {
+            RunError::Connection {
+                message: error.to_string(),
+            }
+        }
+    }
+}
+
+impl From<serde_json::Error> for RunError {
+    fn from(error: serde_json::Error) -> Self {
+        RunError::Decode {
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Result of a single PSI run.
+#[derive(Debug)]
+pub enum RunOutcome {
+    Success(Box<PSIResult>),
+    Failure(RunError),
+}
+
+impl RunOutcome {
+    pub fn result(&self) -> Option<&LHResult> {
+        self.response().map(|response| &response.lighthouse_result)
+    }
+
+    pub fn response(&self) -> Option<&PSIResult> {
+        match self {
+            RunOutcome::Success(response) => Some(response),
+            RunOutcome::Failure(_) => None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&RunError> {
+        match self {
+            RunOutcome::Success(_) => None,
+            RunOutcome::Failure(error) => Some(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod outcome_tests {
+    use super::RunError;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn from_response() {
+        let quota_body = r#"{"error": {"code": 429, "message": "Quota exceeded", "status": "RESOURCE_EXHAUSTED"}}"#;
+        let api_body =
+            r#"{"error": {"code": 500, "message": "Backend error", "status": "INTERNAL"}}"#;
+        let lighthouse_body = r#"{"error": {"code": 500, "message": "Lighthouse returned error: NO_FCP. The page did not paint any content. Please ensure you keep the browser window in the foreground during the load and try again. (NO_FCP)", "errors": [{"message": "Lighthouse returned error: NO_FCP. The page did not paint any content. Please ensure you keep the browser window in the foreground during the load and try again. (NO_FCP)", "domain": "lighthouse", "reason": "lighthouseUserError"}], "status": "INTERNAL"}}"#;
+
+        assert!(matches!(
+            RunError::from_response(StatusCode::TOO_MANY_REQUESTS, quota_body),
+            RunError::QuotaExceeded { .. }
+        ));
+        assert!(matches!(
+            RunError::from_response(StatusCode::INTERNAL_SERVER_ERROR, api_body),
+            RunError::Api { status: 500, .. }
+        ));
+        assert!(matches!(
+            RunError::from_response(StatusCode::INTERNAL_SERVER_ERROR, lighthouse_body),
+            RunError::Lighthouse { code, .. } if code == "NO_FCP"
+        ));
+        assert!(matches!(
+            RunError::from_response(StatusCode::BAD_GATEWAY, "<html></html>"),
+            RunError::Http { status: 502 }
+        ));
+    }
+
+    #[tokio::test]
+    async fn from_reqwest_error() {
+        let error = reqwest::get("http://127.0.0.1:9/?url=https%3A%2F%2Fa.example%2F&key=SECRET")
+            .await
+            .unwrap_err();
+
+        match RunError::from(error) {
+            RunError::Connection { message } => assert!(!message.contains("SECRET")),
+            error => panic!("{:?} should be a connection error", error),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::statistics::percentiles;
+use crate::{PSIResultValues, PSIStatisticResult};
+
+/// Tukey's fences multiplier.
+const IQR_MULTIPLIER: f64 = 1.5_f64;
+/// Modified z-score above which a value is an outlier, Iglewicz and Hoaglin recommendation.
+const MAD_THRESHOLD: f64 = 3.5_f64;
+/// Makes the MAD a consistent estimator of the standard deviation for normal data.
+const MAD_SCALE: f64 = 0.6745_f64;
+
+#[derive(Debug, Clone, Copy)]
+pub enum OutlierMethod {
+    /// Values outside [P25 - 1.5 * IQR, P75 + 1.5 * IQR].
+    Iqr,
+    /// Values with a modified z-score, based on the median absolute deviation, above 3.5.
+    Mad,
+    /// The given fraction of the lowest and of the highest values, as in a trimmed mean.
+    Trim(f64),
+}
+
+impl std::fmt::Display for OutlierMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            OutlierMethod::Iqr => write!(f, "iqr"),
+            OutlierMethod::Mad => write!(f, "mad"),
+            OutlierMethod::Trim(fraction) => write!(f, "trim {fraction}", fraction = fraction),
+        }
+    }
+}
+
+/// Positions of the outliers in `data`, in ascending order.
+pub fn detect(data: &[f64], method: OutlierMethod) -> Vec<usize> {
+    match method {
+        OutlierMethod::Iqr => {
+            let percentiles = percentiles(data);
+            let iqr = percentiles.p75 - percentiles.p25;
+            let lower_fence = percentiles.p25 - IQR_MULTIPLIER * iqr;
+            let upper_fence = percentiles.p75 + IQR_MULTIPLIER * iqr;
+
+            positions(data, |value| value < lower_fence || value > upper_fence)
+        }
+        OutlierMethod::Mad => {
+            let median = percentiles(data).median;
+            let deviations = data
+                .iter()
+                .map(|value| (value - median).abs())
+                .collect::<Vec<_>>();
+            let mad = percentiles(&deviations).median;
+
+            // Half or more of the values are equal, nothing stands out
+            if mad == 0_f64 {
+                return Vec::new();
+            }
+
+            positions(data, |value| {
+                (MAD_SCALE * (value - median) / mad).abs() > MAD_THRESHOLD
+            })
+        }
+        OutlierMethod::Trim(fraction) => {
+            let trimmed = (data.len() as f64 * fraction).floor() as usize;
+            let mut sorted_positions = (0..data.len()).collect::<Vec<_>>();
+            sorted_positions.sort_by(|a, b| data[*a].partial_cmp(&data[*b]).unwrap());
+
+            let mut outliers = sorted_positions[..trimmed]
+                .iter()
+                .chain(&sorted_positions[data.len() - trimmed..])
+                .copied()
+                .collect::<Vec<_>>();
+            outliers.sort_unstable();
+
+            outliers
+        }
+    }
+}
+
+fn positions(data: &[f64], is_outlier: impl Fn(f64) -> bool) -> Vec<usize> {
+    data.iter()
+        .enumerate()
+        .filter(|(_, value)| is_outlier(**value))
+        .map(|(position, _)| position)
+        .collect()
+}
+
+pub fn calculate_outliers(
+    page_results: &PSIResultValues,
+    method: OutlierMethod,
+) -> PSIStatisticResult<Vec<usize>> {
+    page_results.map(|values| detect(values, method))
+}
+
+fn exclude(data: &[f64], outliers: &[usize]) -> Vec<f64> {
+    data.iter()
+        .enumerate()
+        .filter(|(position, _)| !outliers.contains(position))
+        .map(|(_, value)| *value)
+        .collect()
+}
+
+/// Copy of the page results without the outliers of each metric.
+pub fn without_outliers(
+    page_results: &PSIResultValues,
+    outliers: &PSIStatisticResult<Vec<usize>>,
+) -> PSIResultValues {
+    page_results.map_metrics(|name, values| exclude(values, outliers.metric(name).unwrap()))
+}
+
+#[cfg(test)]
+mod outliers_tests {
+    use super::OutlierMethod;
+
+    #[test]
+    fn detect() {
+        let data = [
+            2000_f64, 2100_f64, 1900_f64, 2050_f64, 9000_f64, 1950_f64, 2020_f64,
+        ];
+
+        assert_eq!(vec![4], super::detect(&data, OutlierMethod::Iqr));
+        assert_eq!(vec![4], super::detect(&data, OutlierMethod::Mad));
+        assert_eq!(vec![2, 4], super::detect(&data, OutlierMethod::Trim(0.15)));
+        assert!(super::detect(&[1_f64, 1_f64, 1_f64], OutlierMethod::Mad).is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::utils::check_file_availability;
+
+/// What to do when the output file already exists.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteMode {
+    /// Writes to a new file instead, adding ` (1)`, ` (2)`... to its name.
+    NewFile,
+    Overwrite,
+    Append,
+}
+
+/// Whether the file exists and has some content to append to.
+pub fn has_content(path: &str) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0)
+}
+
+/// Opens the output file, returns it with its final path, which differs from `path` when a new
+/// file is created next to an existing one.
+pub fn open(path: &str, mode: WriteMode) -> (File, String) {
+    let path = match mode {
+        WriteMode::NewFile => check_file_availability(path),
+        WriteMode::Overwrite | WriteMode::Append => path.to_string(),
+    };
+
+    if let Some(dir) = Path::new(&path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(dir).expect("Output directory should be writable");
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(mode == WriteMode::Append)
+        .truncate(mode != WriteMode::Append)
+        .open(&path)
+        .expect("Output file should be writable");
+
+    (file, path)
+}
+
+/// Writes a Markdown or JSON report to the output file, or to stdout without one. Appended
+/// reports follow the existing content of the file.
+pub fn write_report(path: Option<&str>, mode: WriteMode, report: &str) {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            print!("{}", report);
+            return;
+        }
+    };
+
+    let (mut file, _) = open(path, mode);
+
+    file.write_all(report.as_bytes())
+        .expect("Output file should be writable");
+}
+
+/// Position in `header` of each column of `file_header`, `None` for the columns it doesn't
+/// have. Rows appended to an existing CSV follow its header.
+pub fn csv_columns(file_header: &[String], header: &[String]) -> Vec<Option<usize>> {
+    file_header
+        .iter()
+        .map(|column| header.iter().position(|name| name == column))
+        .collect()
+}
+
+#[cfg(test)]
+mod output_tests {
+    use super::WriteMode;
+
+    #[test]
+    fn append_report() {
+        let path = std::env::temp_dir().join(format!("psi-test-output-{}.md", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        super::write_report(Some(path), WriteMode::Overwrite, "first\n");
+        super::write_report(Some(path), WriteMode::Append, "second\n");
+        assert_eq!("first\nsecond\n", std::fs::read_to_string(path).unwrap());
+
+        super::write_report(Some(path), WriteMode::Overwrite, "third\n");
+        assert_eq!("third\n", std::fs::read_to_string(path).unwrap());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn csv_columns() {
+        let columns = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![Some(0), Some(2), None],
+            super::csv_columns(
+                &columns(&["Store", "Mobile - Mean", "Old column"]),
+                &columns(&["Store", "Desktop - Mean", "Mobile - Mean"]),
+            )
+        );
+    }
+}
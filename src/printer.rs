@@ -1,187 +1,989 @@
 use csv::Writer;
-use serde::Serialize;
-use std::error::Error;
-use std::fs::File;
-use std::io;
-
-use crate::utils::check_file_availability;
-use crate::PSIStatisticResult;
-
-fn print_table_result(
-    page_mean: &PSIStatisticResult<f64>,
-    page_std_deviation: &PSIStatisticResult<f64>,
-    page_confidence_interval: &PSIStatisticResult<(f64, f64)>,
+use std::fs::{self, File};
+
+use crate::budget::BudgetResult;
+use crate::compare::MetricComparison;
+use crate::field_data::{FieldExperience, FieldMetric};
+use crate::metrics;
+use crate::opportunities::{AuditSummary, OpportunityReport};
+use crate::outcome::RunError;
+use crate::output::{self, WriteMode};
+use crate::resources::ResourceReport;
+use crate::statistics::{PageStatistics, Percentiles};
+use crate::third_parties::ThirdPartySummary;
+use crate::{BatchRow, BatchStrategyResult, CompareSample, PSIStatisticResult, TestResult};
+
+/// `println!` into the report being printed.
+macro_rules! outln {
+    ($out:expr) => {
+        $out.push('\n')
+    };
+    ($out:expr, $($arg:tt)*) => {{
+        $out.push_str(&format!($($arg)*));
+        $out.push('\n');
+    }};
+}
+
+fn print_table_result(out: &mut String, statistics: &PageStatistics, confidence_level: f64) {
+    outln!(
+        out,
+        "| Metric | Mean | Standard deviation | Variance | Confidence Interval ({level}%) |",
+        level = confidence_level * 100_f64
+    );
+    outln!(out, "|--------|--------|--------|--------|--------|");
+
+    for (name, mean) in statistics.mean.iter() {
+        let metric = metrics::find(name);
+        let confidence_interval = statistics.confidence_interval.metric(name).unwrap();
+
+        outln!(out,
+            "| {metric} | {mean:.decimals$} | {std_deviation:.spread_decimals$} | {variance:.spread_decimals$} | [{ci_min:.spread_decimals$}, {ci_max:.spread_decimals$}] |",
+            metric = metric.display_name,
+            mean = mean,
+            std_deviation = statistics.std_deviation.metric(name).unwrap(),
+            variance = statistics.variance.metric(name).unwrap(),
+            ci_min = confidence_interval.0,
+            ci_max = confidence_interval.1,
+            decimals = metric.decimals,
+            spread_decimals = metric.spread_decimals(),
+        );
+    }
+}
+
+fn print_percentile_row(
+    out: &mut String,
+    metric: &str,
+    percentiles: &Percentiles,
+    decimals: usize,
+) {
+    outln!(out,
+        "| {metric} | {min:.decimals$} | {p25:.decimals$} | {median:.decimals$} | {p75:.decimals$} | {p90:.decimals$} | {p95:.decimals$} | {max:.decimals$} |",
+        metric = metric,
+        min = percentiles.min,
+        p25 = percentiles.p25,
+        median = percentiles.median,
+        p75 = percentiles.p75,
+        p90 = percentiles.p90,
+        p95 = percentiles.p95,
+        max = percentiles.max,
+        decimals = decimals,
+    );
+}
+
+fn print_percentiles_table(out: &mut String, page_percentiles: &PSIStatisticResult<Percentiles>) {
+    outln!(
+        out,
+        "| Metric | Min | P25 | Median | P75 | P90 | P95 | Max |"
+    );
+    outln!(
+        out,
+        "|--------|--------|--------|--------|--------|--------|--------|--------|"
+    );
+
+    for (name, percentiles) in page_percentiles.iter() {
+        let metric = metrics::find(name);
+
+        print_percentile_row(out, &metric.display_name, percentiles, metric.decimals);
+    }
+}
+
+fn print_field_row(
+    out: &mut String,
+    metric: &str,
+    field_metric: &Option<FieldMetric>,
+    lab_p75: Option<f64>,
+    decimals: usize,
+) {
+    let lab_p75 = match lab_p75 {
+        Some(value) => format!("{value:.decimals$}", value = value, decimals = decimals),
+        None => "-".to_string(),
+    };
+
+    match field_metric {
+        Some(field_metric) => outln!(out,
+            "| {metric} | {p75:.decimals$} | {lab_p75} | {category} | {fast:.1}% | {average:.1}% | {slow:.1}% |",
+            metric = metric,
+            p75 = field_metric.p75,
+            lab_p75 = lab_p75,
+            category = field_metric.category.as_deref().unwrap_or("-"),
+            fast = field_metric.fast * 100_f64,
+            average = field_metric.average * 100_f64,
+            slow = field_metric.slow * 100_f64,
+            decimals = decimals,
+        ),
+        None => outln!(out,
+            "| {metric} | - | {lab_p75} | - | - | - | - |",
+            metric = metric,
+            lab_p75 = lab_p75
+        ),
+    }
+}
+
+/// Real-user p75 side by side with the lab p75 of the same metric, when Lighthouse has it.
+fn print_field_table(
+    out: &mut String,
+    scope: &str,
+    field_experience: &Option<FieldExperience>,
+    lab_percentiles: &PSIStatisticResult<Percentiles>,
+) {
+    let field_experience = match field_experience {
+        Some(field_experience) => field_experience,
+        None => {
+            outln!(out, "No field data for this {scope}", scope = scope);
+            return;
+        }
+    };
+
+    outln!(
+        out,
+        "Field data (CrUX) of the {scope}, overall {category}",
+        scope = scope,
+        category = field_experience.overall_category.as_deref().unwrap_or("-")
+    );
+    outln!(
+        out,
+        "| Metric | Field P75 | Lab P75 | Category | Fast | Average | Slow |"
+    );
+    outln!(
+        out,
+        "|--------|--------|--------|--------|--------|--------|--------|"
+    );
+
+    print_field_row(
+        out,
+        "Largest Contentful Paint (LCP)",
+        &field_experience.largest_contentful_paint,
+        lab_percentiles
+            .metric("largest_contentful_paint")
+            .map(|percentiles| percentiles.p75),
+        2,
+    );
+    print_field_row(
+        out,
+        "Cumulative Layout shift (CLS)",
+        &field_experience.cumulative_layout_shift,
+        lab_percentiles
+            .metric("cumulative_layout_shift")
+            .map(|percentiles| percentiles.p75),
+        2,
+    );
+    print_field_row(
+        out,
+        "Interaction to Next Paint (INP)",
+        &field_experience.interaction_to_next_paint,
+        None,
+        2,
+    );
+    print_field_row(
+        out,
+        "First Contentful Paint (FCP)",
+        &field_experience.first_contentful_paint,
+        lab_percentiles
+            .metric("first_contentful_paint")
+            .map(|percentiles| percentiles.p75),
+        2,
+    );
+    print_field_row(
+        out,
+        "Time to First Byte (TTFB)",
+        &field_experience.time_to_first_byte,
+        lab_percentiles
+            .metric("server_response_time")
+            .map(|percentiles| percentiles.p75),
+        2,
+    );
+}
+
+fn format_optional(value: Option<f64>, decimals: usize) -> String {
+    match value {
+        Some(value) => format!("{value:.decimals$}", value = value, decimals = decimals),
+        None => "-".to_string(),
+    }
+}
+
+fn format_top_urls(summary: &AuditSummary) -> String {
+    summary
+        .top_urls
+        .iter()
+        .map(|offending_url| {
+            format!(
+                "{url} ({runs})",
+                url = offending_url.url,
+                runs = offending_url.runs
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_opportunities_tables(out: &mut String, report: &OpportunityReport) {
+    outln!(out, "Opportunities");
+    if report.opportunities.is_empty() {
+        outln!(out, "No opportunity fired");
+    } else {
+        outln!(
+            out,
+            "| Audit | Fired | Median savings (ms) | Median savings (bytes) | Top URLs |"
+        );
+        outln!(out, "|--------|--------|--------|--------|--------|");
+
+        for summary in &report.opportunities {
+            outln!(out,
+                "| {title} ({id}) | {runs_fired}/{runs} | {savings_ms} | {savings_bytes} | {top_urls} |",
+                title = summary.title,
+                id = summary.id,
+                runs_fired = summary.runs_fired,
+                runs = summary.runs,
+                savings_ms = format_optional(summary.median_savings_ms, 0),
+                savings_bytes = format_optional(summary.median_savings_bytes, 0),
+                top_urls = format_top_urls(summary),
+            );
+        }
+    }
+
+    outln!(out);
+    outln!(out, "Diagnostics");
+    if report.diagnostics.is_empty() {
+        outln!(out, "No diagnostic fired");
+    } else {
+        outln!(out, "| Audit | Fired | Median score | Top URLs |");
+        outln!(out, "|--------|--------|--------|--------|");
+
+        for summary in &report.diagnostics {
+            outln!(
+                out,
+                "| {title} ({id}) | {runs_fired}/{runs} | {score} | {top_urls} |",
+                title = summary.title,
+                id = summary.id,
+                runs_fired = summary.runs_fired,
+                runs = summary.runs,
+                score = format_optional(summary.median_score, 2),
+                top_urls = format_top_urls(summary),
+            );
+        }
+    }
+}
+
+fn print_third_parties_table(out: &mut String, third_parties: &[ThirdPartySummary]) {
+    outln!(out, "Third parties");
+    if third_parties.is_empty() {
+        outln!(out, "No third party loaded");
+        return;
+    }
+
+    outln!(out, "| Entity | Loaded | Median blocking time (ms) | Blocking time variance | Median main-thread time (ms) | Median transfer size (bytes) |");
+    outln!(
+        out,
+        "|--------|--------|--------|--------|--------|--------|"
+    );
+
+    for summary in third_parties {
+        outln!(out,
+            "| {entity} | {runs_loaded}/{runs} | {blocking_time:.0} | {variance:.2} | {main_thread_time:.0} | {transfer_size:.0} |",
+            entity = summary.entity,
+            runs_loaded = summary.runs_loaded,
+            runs = summary.runs,
+            blocking_time = summary.median_blocking_time,
+            variance = summary.blocking_time_variance,
+            main_thread_time = summary.median_main_thread_time,
+            transfer_size = summary.median_transfer_size,
+        );
+    }
+}
+
+fn print_resources_tables(out: &mut String, report: &ResourceReport) {
+    outln!(out, "Resources");
+    outln!(out, "| Resource type | Median requests | Requests range | Median transfer size (bytes) | Transfer size range (bytes) | Varies |");
+    outln!(
+        out,
+        "|--------|--------|--------|--------|--------|--------|"
+    );
+
+    for summary in &report.resources {
+        outln!(out,
+            "| {resource_type} | {median_requests:.0} | {min_requests:.0} - {max_requests:.0} | {median_transfer_size:.0} | {min_transfer_size:.0} - {max_transfer_size:.0} | {varies} |",
+            resource_type = summary.resource_type,
+            median_requests = summary.median_requests,
+            min_requests = summary.min_requests,
+            max_requests = summary.max_requests,
+            median_transfer_size = summary.median_transfer_size,
+            min_transfer_size = summary.min_transfer_size,
+            max_transfer_size = summary.max_transfer_size,
+            varies = if summary.varies { "yes" } else { "no" },
+        );
+    }
+
+    outln!(out);
+    outln!(out, "Unstable requests");
+    if report.unstable_requests.is_empty() {
+        outln!(out, "Every run made the same requests");
+        return;
+    }
+
+    outln!(out, "| URL | Resource type | Requested | Failed |");
+    outln!(out, "|--------|--------|--------|--------|");
+
+    for request in &report.unstable_requests {
+        outln!(
+            out,
+            "| {url} | {resource_type} | {runs}/{total_runs} | {runs_failed} |",
+            url = request.url,
+            resource_type = request.resource_type.as_deref().unwrap_or("-"),
+            runs = request.runs,
+            total_runs = report.runs,
+            runs_failed = request.runs_failed,
+        );
+    }
+}
+
+fn print_outlier_row(out: &mut String, metric: &str, runs: &[usize]) {
+    outln!(
+        out,
+        "| {metric} | {runs} |",
+        metric = metric,
+        runs = runs
+            .iter()
+            .map(|index| index.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+fn print_outliers_table(out: &mut String, runs: &PSIStatisticResult<Vec<usize>>) {
+    outln!(out, "| Metric | Runs |");
+    outln!(out, "|--------|--------|");
+
+    for (name, runs) in runs.iter() {
+        print_outlier_row(out, &metrics::find(name).display_name, runs);
+    }
+}
+
+fn print_budgets_table(out: &mut String, budgets: &[BudgetResult]) {
+    outln!(out, "| Budget | Actual | Result |");
+    outln!(out, "|--------|--------|--------|");
+
+    for result in budgets {
+        outln!(
+            out,
+            "| {budget} | {actual:.3} | {status} |",
+            budget = result.budget,
+            actual = result.actual,
+            status = if result.passed { "pass" } else { "FAIL" },
+        );
+    }
+}
+
+fn print_failed_runs_table(out: &mut String, failed_runs: &[(usize, RunError)]) {
+    outln!(out, "| Run | Error |");
+    outln!(out, "|--------|--------|");
+
+    for (index, error) in failed_runs {
+        outln!(out, "| {index} | {error} |", index = index, error = error);
+    }
+}
+
+pub fn print_md(out: &mut String, page_url: &str, test_result: &TestResult) {
+    if !test_result.failed_runs.is_empty() {
+        outln!(
+            out,
+            "Some tests failed, the number of success tests is: {}",
+            test_result.success_runs
+        );
+    }
+    outln!(out, "Page result - {url}", url = page_url);
+    if let Some(report) = &test_result.adaptive_report {
+        outln!(out,
+            "Adaptive sampling {status} after {runs} runs: {metric} CI half-width {half_width:.4} (target {target:.4})",
+            status = if report.target_reached { "converged" } else { "stopped at max runs" },
+            runs = report.runs,
+            metric = report.metric,
+            half_width = report.half_width,
+            target = report.target_half_width,
+        );
+    }
+    print_table_result(out, &test_result.statistics, test_result.confidence_level);
+    outln!(out);
+    print_percentiles_table(out, &test_result.statistics.percentiles);
+
+    outln!(out);
+    print_opportunities_tables(out, &test_result.opportunities);
+    outln!(out);
+    print_third_parties_table(out, &test_result.third_parties);
+    outln!(out);
+    print_resources_tables(out, &test_result.resources);
+    outln!(out);
+    print_field_table(
+        out,
+        "page",
+        &test_result.field_data.page,
+        &test_result.statistics.percentiles,
+    );
+    outln!(out);
+    print_field_table(
+        out,
+        "origin",
+        &test_result.field_data.origin,
+        &test_result.statistics.percentiles,
+    );
+
+    if let Some(outliers) = &test_result.outliers {
+        outln!(out);
+        outln!(out, "Outlier runs ({method})", method = outliers.method);
+        print_outliers_table(out, &outliers.runs);
+        outln!(out);
+        outln!(out, "Page result without outliers - {url}", url = page_url);
+        print_table_result(out, &outliers.statistics, test_result.confidence_level);
+        outln!(out);
+        print_percentiles_table(out, &outliers.statistics.percentiles);
+    }
+
+    if !test_result.budgets.is_empty() {
+        outln!(out);
+        outln!(out, "Budgets");
+        print_budgets_table(out, &test_result.budgets);
+    }
+
+    if let Some(check) = &test_result.baseline {
+        outln!(out);
+        outln!(
+            out,
+            "Against baseline {file} - A: {url} ({strategy}, {created_at}) / B: this run",
+            file = check.file,
+            url = check.baseline_url,
+            strategy = check.baseline_strategy,
+            created_at = check.baseline_created_at,
+        );
+        print_compare_table(out, &check.comparison, check.alpha);
+        outln!(out);
+        if check.regressions.is_empty() {
+            outln!(
+                out,
+                "No metric regressed, Mann-Whitney U p-values with the Holm correction"
+            );
+        } else {
+            outln!(
+                out,
+                "Regressed metrics, Mann-Whitney U p-values with the Holm correction: {metrics}",
+                metrics = check.regressions.join(", ")
+            );
+        }
+    }
+
+    if !test_result.failed_runs.is_empty() {
+        outln!(out);
+        outln!(out, "Failed runs");
+        print_failed_runs_table(out, &test_result.failed_runs);
+    }
+}
+
+pub fn statistics_json(statistics: &PageStatistics) -> serde_json::Value {
+    statistics
+        .mean
+        .iter()
+        .map(|(name, mean)| {
+            let statistic = serde_json::json!({
+                "mean": mean,
+                "std_dev": statistics.std_deviation.metric(name),
+                "variance": statistics.variance.metric(name),
+                "confidence_interval": statistics.confidence_interval.metric(name),
+                "percentiles": statistics.percentiles.metric(name),
+                "unit": metrics::find(name).unit,
+            });
+
+            (name.to_string(), statistic)
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn failed_runs_json(failed_runs: &[(usize, RunError)]) -> serde_json::Value {
+    failed_runs
+        .iter()
+        .map(|(index, error)| serde_json::json!({ "run": index, "error": error }))
+        .collect()
+}
+
+pub fn print_json(out: &mut String, page_url: &str, test_result: &TestResult) {
+    let mut json = statistics_json(&test_result.statistics);
+    json["url"] = serde_json::json!(page_url);
+    json["success_runs"] = serde_json::json!(test_result.success_runs);
+    json["confidence_level"] = serde_json::json!(test_result.confidence_level);
+    json["failed_runs"] = failed_runs_json(&test_result.failed_runs);
+    json["adaptive_sampling"] = serde_json::json!(test_result.adaptive_report);
+    json["budgets"] = serde_json::json!(test_result.budgets);
+    json["field_data"] = serde_json::json!(test_result.field_data);
+    json["opportunities"] = serde_json::json!(test_result.opportunities.opportunities);
+    json["diagnostics"] = serde_json::json!(test_result.opportunities.diagnostics);
+    json["third_parties"] = serde_json::json!(test_result.third_parties);
+    json["resources"] = serde_json::json!(test_result.resources);
+
+    if let Some(check) = &test_result.baseline {
+        json["baseline"] = serde_json::json!({
+            "file": check.file,
+            "url": check.baseline_url,
+            "strategy": check.baseline_strategy,
+            "created_at": check.baseline_created_at,
+            "alpha": check.alpha,
+            "metrics": check.comparison,
+            "regressions": check.regressions,
+        });
+    }
+
+    if let Some(outliers) = &test_result.outliers {
+        json["outliers"] = serde_json::json!({
+            "method": outliers.method.to_string(),
+            "runs": outliers.runs,
+            "without_outliers": statistics_json(&outliers.statistics),
+        });
+    }
+
+    outln!(out, "{}", serde_json::to_string_pretty(&json).unwrap())
+}
+
+fn format_p_value(p_value: f64, alpha: f64) -> String {
+    if p_value < alpha {
+        format!("{p_value:.4}*", p_value = p_value)
+    } else {
+        format!("{p_value:.4}", p_value = p_value)
+    }
+}
+
+fn print_compare_row(
+    out: &mut String,
+    metric: &str,
+    comparison: &MetricComparison,
+    decimals: usize,
+    alpha: f64,
 ) {
-    println!("| Metric | Mean | Standard deviation | Confidence Interval (95%) |");
-    println!("|--------|--------|--------|--------|");
-
-    println!(
-        "| Cumulative Layout shift (CLS) | {mean:.2} | {std_deviation:.2} | [{ci_min:.2}, {ci_max:.2}] |",
-        mean = page_mean.cumulative_layout_shift,
-        std_deviation = page_std_deviation.cumulative_layout_shift,
-        ci_min = page_confidence_interval.cumulative_layout_shift.0,
-        ci_max = page_confidence_interval.cumulative_layout_shift.1,
-    );
-    println!(
-        "| First Contentful Paint (FCP) | {mean:.2} | {std_deviation:.2} | [{ci_min:.2}, {ci_max:.2}] |",
-        mean = page_mean.first_contentful_paint,
-        std_deviation = page_std_deviation.first_contentful_paint,
-        ci_min = page_confidence_interval.first_contentful_paint.0,
-        ci_max = page_confidence_interval.first_contentful_paint.1,
-    );
-    println!(
-        "| Largest Contentful Paint (LCP) | {mean:.2} | {std_deviation:.2} | [{ci_min:.2}, {ci_max:.2}] |",
-        mean = page_mean.largest_contentful_paint,
-        std_deviation = page_std_deviation.largest_contentful_paint,
-
-        ci_min = page_confidence_interval.largest_contentful_paint.0,
-        ci_max = page_confidence_interval.largest_contentful_paint.1,
-    );
-    println!(
-        "| Time to Interactive (TTI) | {mean:.2} | {std_deviation:.2} | [{ci_min:.2}, {ci_max:.2}] |",
-        mean = page_mean.time_to_interactive,
-        std_deviation = page_std_deviation.time_to_interactive,
-
-        ci_min = page_confidence_interval.time_to_interactive.0,
-        ci_max = page_confidence_interval.time_to_interactive.1,
-    );
-    println!(
-        "| Total Blocking Time (TBT) | {mean:.2} | {std_deviation:.2} | [{ci_min:.2}, {ci_max:.2}] |",
-        mean = page_mean.total_blocking_time,
-        std_deviation = page_std_deviation.total_blocking_time,
-
-        ci_min = page_confidence_interval.total_blocking_time.0,
-        ci_max = page_confidence_interval.total_blocking_time.1,
-    );
-    println!(
-        "| Performance score | {mean:.3} | {std_deviation:.6} | [{ci_min:.6}, {ci_max:.6}] |",
-        mean = page_mean.score,
-        std_deviation = page_std_deviation.score,
-        ci_min = page_confidence_interval.score.0,
-        ci_max = page_confidence_interval.score.1,
-    );
-    println!(
-        "| JavaScript Execution Time | {mean:.2} | {std_deviation:.2} | [{ci_min:.2}, {ci_max:.2}] |",
-        mean = page_mean.js_execution_time,
-        std_deviation = page_std_deviation.js_execution_time,
-
-        ci_min = page_confidence_interval.js_execution_time.0,
-        ci_max = page_confidence_interval.js_execution_time.1,
-    );
-    println!(
-        "| Speed Index | {mean:.2} | {std_deviation:.2} | [{ci_min:.2}, {ci_max:.2}] |",
-        mean = page_mean.speed_index,
-        std_deviation = page_std_deviation.speed_index,
-        ci_min = page_confidence_interval.speed_index.0,
-        ci_max = page_confidence_interval.speed_index.1,
-    );
-}
-
-pub fn print_md(
-    page_url: &str,
-    success_runs: i8,
-    page_mean: &PSIStatisticResult<f64>,
-    page_std_deviation: &PSIStatisticResult<f64>,
-    page_confidence_interval: &PSIStatisticResult<(f64, f64)>,
+    outln!(out,
+        "| {metric} | {mean_a:.decimals$} | {mean_b:.decimals$} | {delta:+.decimals$} | {percent_change} | {welch} | {mann_whitney} | {cohens_d:.2} | {cliffs_delta:.2} |",
+        metric = metric,
+        mean_a = comparison.mean_a,
+        mean_b = comparison.mean_b,
+        delta = comparison.delta,
+        percent_change = comparison
+            .percent_change
+            .map_or("-".to_string(), |percent_change| format!(
+                "{percent_change:+.2}%",
+                percent_change = percent_change
+            )),
+        welch = format_p_value(comparison.welch_p_value, alpha),
+        mann_whitney = format_p_value(comparison.mann_whitney_p_value, alpha),
+        cohens_d = comparison.cohens_d,
+        cliffs_delta = comparison.cliffs_delta,
+        decimals = decimals,
+    );
+}
+
+/// Every run has a score, its number of values is the number of successful runs.
+fn sample_runs(sample: &CompareSample) -> usize {
+    sample.values.metric("score").map_or(0, Vec::len)
+}
+
+pub fn print_compare_md(
+    out: &mut String,
+    sample_a: &CompareSample,
+    sample_b: &CompareSample,
+    comparison: &PSIStatisticResult<MetricComparison>,
+    alpha: f64,
 ) {
-    println!(
-        "Some tests failed, the number of success tests is: {}",
-        success_runs
+    outln!(
+        out,
+        "Compare - A: {source_a} ({runs_a} runs) / B: {source_b} ({runs_b} runs)",
+        source_a = sample_a.source,
+        runs_a = sample_runs(sample_a),
+        source_b = sample_b.source,
+        runs_b = sample_runs(sample_b),
     );
-    println!("Page result - {url}", url = page_url);
-    print_table_result(page_mean, page_std_deviation, page_confidence_interval);
+    print_compare_table(out, comparison, alpha);
+
+    for (label, sample) in [("A", sample_a), ("B", sample_b)] {
+        if sample.failed_runs.is_empty() {
+            continue;
+        }
+
+        outln!(out);
+        outln!(out, "Failed runs of {label}", label = label);
+        print_failed_runs_table(out, &sample.failed_runs);
+    }
 }
 
-pub fn print_json(
-    page_url: &str,
-    success_runs: i8,
-    page_mean: &PSIStatisticResult<f64>,
-    page_std_deviation: &PSIStatisticResult<f64>,
-    page_confidence_interval: &PSIStatisticResult<(f64, f64)>,
+fn print_compare_table(
+    out: &mut String,
+    comparison: &PSIStatisticResult<MetricComparison>,
+    alpha: f64,
+) {
+    outln!(out,
+        "| Metric | A Mean | B Mean | Delta | Change | Welch p-value | Mann-Whitney p-value | Cohen's d | Cliff's delta |"
+    );
+    outln!(
+        out,
+        "|--------|--------|--------|--------|--------|--------|--------|--------|--------|"
+    );
+
+    for (name, metric_comparison) in comparison.iter() {
+        let metric = metrics::find(name);
+
+        print_compare_row(
+            out,
+            &metric.display_name,
+            metric_comparison,
+            metric.decimals,
+            alpha,
+        );
+    }
+    outln!(out);
+    outln!(
+        out,
+        "* p-value below {alpha}, the difference is statistically significant.",
+        alpha = alpha
+    );
+}
+
+fn compare_sample_json(sample: &CompareSample) -> serde_json::Value {
+    serde_json::json!({
+        "source": sample.source,
+        "success_runs": sample_runs(sample),
+        "failed_runs": failed_runs_json(&sample.failed_runs),
+    })
+}
+
+pub fn print_compare_json(
+    out: &mut String,
+    sample_a: &CompareSample,
+    sample_b: &CompareSample,
+    comparison: &PSIStatisticResult<MetricComparison>,
+    alpha: f64,
 ) {
     let json = serde_json::json!({
-      "url": page_url,
-      "success_runs": success_runs,
-      "cumulative_layout_shift": {
-         "mean": page_mean.cumulative_layout_shift,
-         "std_dev": page_std_deviation.cumulative_layout_shift,
-         "confidence_interval": page_confidence_interval.cumulative_layout_shift,
-      },
-     "first_contentful_paint": {
-         "mean": page_mean.first_contentful_paint,
-         "std_dev": page_std_deviation.first_contentful_paint ,
-         "confidence_interval": page_confidence_interval.first_contentful_paint,
-      },
-     "largest_contentful_paint": {
-         "mean": page_mean.largest_contentful_paint,
-         "std_dev": page_std_deviation.largest_contentful_paint ,
-         "confidence_interval": page_confidence_interval.largest_contentful_paint,
-      },
-     "time_to_interactive": {
-         "mean": page_mean.time_to_interactive,
-         "std_dev": page_std_deviation.time_to_interactive,
-         "confidence_interval": page_confidence_interval.time_to_interactive,
-     },
-     "total_blocking_time": {
-         "mean": page_mean.total_blocking_time,
-         "std_dev": page_std_deviation.total_blocking_time,
-         "confidence_interval": page_confidence_interval.total_blocking_time,
-     },
-     "score": {
-         "mean": page_mean.score,
-         "std_dev": page_std_deviation.score,
-         "confidence_interval": page_confidence_interval.score,
-     },
-     "js_execution_time": {
-         "mean": page_mean.js_execution_time,
-         "std_dev": page_std_deviation.js_execution_time,
-         "confidence_interval": page_confidence_interval.js_execution_time,
-     },
-     "speed_index" :{
-         "mean": page_mean.speed_index,
-         "std_dev": page_std_deviation.speed_index,
-         "confidence_interval": page_confidence_interval.speed_index,
-     }
+        "a": compare_sample_json(sample_a),
+        "b": compare_sample_json(sample_b),
+        "alpha": alpha,
+        "metrics": comparison,
     });
-    println!("{}", serde_json::to_string_pretty(&json).unwrap())
+
+    outln!(out, "{}", serde_json::to_string_pretty(&json).unwrap())
 }
 
-#[derive(Serialize)]
-pub struct Row<'a> {
-    #[serde(rename = "Store")]
-    pub url: &'a str,
+const BATCH_STRATEGIES: [&str; 2] = ["Desktop", "Mobile"];
+/// Score columns of the first batch CSV format, at their original place after the URL.
+const BATCH_SCORE_COLUMNS: [&str; 2] = ["Mean", "Median"];
+const BATCH_METRIC_COLUMNS: [&str; 11] = [
+    "Mean", "Min", "P25", "Median", "P75", "P90", "P95", "Max", "Std dev", "CI min", "CI max",
+];
 
-    #[serde(rename = "Desktop - Mean")]
-    pub d_mean: f64,
+/// Failed runs count and their distinct errors, `None` when every run succeeded.
+fn failure_reason(result: &BatchStrategyResult) -> Option<String> {
+    if result.failed_runs.is_empty() {
+        return None;
+    }
 
-    #[serde(rename = "Desktop - Median")]
-    pub d_median: f64,
+    let mut errors = Vec::new();
+    for (_, error) in &result.failed_runs {
+        let error = error.to_string();
 
-    #[serde(rename = "Mobile - Mean")]
-    pub m_mean: f64,
+        if !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
+
+    Some(format!(
+        "{failed} of {runs} runs failed: {errors}",
+        failed = result.failed_runs.len(),
+        runs = result.failed_runs.len() + result.success_runs,
+        errors = errors.join("; ")
+    ))
+}
+
+fn batch_csv_header(metric_names: &[String]) -> Vec<String> {
+    let mut header = vec!["Store".to_string()];
+
+    for strategy in BATCH_STRATEGIES {
+        header.extend(
+            BATCH_SCORE_COLUMNS
+                .iter()
+                .map(|column| format!("{strategy} - {column}")),
+        );
+    }
+
+    for strategy in BATCH_STRATEGIES {
+        header.push(format!("{strategy} - Success runs"));
+        header.push(format!("{strategy} - Failure"));
+
+        for name in metric_names {
+            header.extend(
+                BATCH_METRIC_COLUMNS
+                    .iter()
+                    .map(|column| format!("{strategy} - {name} - {column}")),
+            );
+        }
+    }
+
+    // Added last, the columns of existing sheets keep their place
+    header.push("Label".to_string());
+    header.push("Group".to_string());
+
+    header
+}
+
+fn batch_csv_record(row: &BatchRow, metric_names: &[String]) -> Vec<String> {
+    let strategy_results = [&row.desktop, &row.mobile];
+    let mut record = vec![row.entry.url.clone()];
+
+    for result in strategy_results {
+        let values = match result {
+            Some(result) => [
+                *result.statistics.mean.metric("score").unwrap(),
+                result
+                    .statistics
+                    .percentiles
+                    .metric("score")
+                    .unwrap()
+                    .median,
+            ]
+            .iter()
+            .map(f64::to_string)
+            .collect(),
+            // Not tested
+            None => vec![String::new(); BATCH_SCORE_COLUMNS.len()],
+        };
+
+        record.extend(values);
+    }
+
+    for result in strategy_results {
+        let result = match result {
+            Some(result) => result,
+            None => {
+                record.push(String::new());
+                record.push("not tested".to_string());
+                record.extend(vec![
+                    String::new();
+                    metric_names.len() * BATCH_METRIC_COLUMNS.len()
+                ]);
+                continue;
+            }
+        };
+
+        record.push(result.success_runs.to_string());
+        record.push(failure_reason(result).unwrap_or_default());
+
+        for name in metric_names {
+            let statistics = &result.statistics;
+            let percentiles = statistics.percentiles.metric(name).unwrap();
+            let confidence_interval = statistics.confidence_interval.metric(name).unwrap();
+
+            record.extend(
+                [
+                    *statistics.mean.metric(name).unwrap(),
+                    percentiles.min,
+                    percentiles.p25,
+                    percentiles.median,
+                    percentiles.p75,
+                    percentiles.p90,
+                    percentiles.p95,
+                    percentiles.max,
+                    *statistics.std_deviation.metric(name).unwrap(),
+                    confidence_interval.0,
+                    confidence_interval.1,
+                ]
+                .iter()
+                .map(f64::to_string),
+            );
+        }
+    }
+    record.push(row.entry.label.clone().unwrap_or_default());
+    record.push(row.entry.group.clone().unwrap_or_default());
 
-    #[serde(rename = "Mobile - Median")]
-    pub m_median: f64,
+    record
 }
 
-pub struct CSVPrinter {
-    csv_writer: Writer<File>,
+fn batch_strategy_json(result: &Option<BatchStrategyResult>) -> serde_json::Value {
+    let result = match result {
+        Some(result) => result,
+        None => return serde_json::Value::Null,
+    };
+
+    let mut json = statistics_json(&result.statistics);
+    json["success_runs"] = serde_json::json!(result.success_runs);
+    json["failure"] = serde_json::json!(failure_reason(result));
+    json["failed_runs"] = failed_runs_json(&result.failed_runs);
+
+    json
+}
+
+/// Batch output, a CSV row for each page written as soon as it's tested, or a JSON array of
+/// every page written once the batch finished.
+pub enum BatchPrinter {
+    Csv {
+        csv_writer: Box<Writer<File>>,
+        metric_names: Vec<String>,
+        /// Position in the batch record of each column of the file header.
+        columns: Vec<Option<usize>>,
+    },
+    Json {
+        path: String,
+        rows: Vec<serde_json::Value>,
+    },
 }
 
-impl CSVPrinter {
-    pub fn new() -> CSVPrinter {
-        CSVPrinter {
-            csv_writer: Writer::from_path(check_file_availability("./output.csv")).unwrap(),
+impl BatchPrinter {
+    /// Without an output path the batch is written to `./output.csv`, or `./output.json`.
+    pub fn new(
+        json: bool,
+        metrics: &[metrics::Metric],
+        path: Option<&str>,
+        mode: WriteMode,
+    ) -> BatchPrinter {
+        let appending = |path: &str| mode == WriteMode::Append && output::has_content(path);
+
+        if json {
+            let path = path.unwrap_or("./output.json");
+            // Appended rows extend the array of the existing file
+            let rows = if appending(path) {
+                let content =
+                    fs::read_to_string(path).expect("Batch output file should be readable");
+
+                serde_json::from_str(&content)
+                    .expect("Batch output file to append to should be a JSON array")
+            } else {
+                Vec::new()
+            };
+            let (_, path) = output::open(path, mode);
+
+            return BatchPrinter::Json { path, rows };
+        }
+
+        let path = path.unwrap_or("./output.csv");
+        let metric_names = metrics
+            .iter()
+            .map(|metric| metric.name.clone())
+            .collect::<Vec<_>>();
+        let header = batch_csv_header(&metric_names);
+        let file_header = appending(path).then(|| {
+            csv::Reader::from_path(path)
+                .and_then(|mut reader| reader.headers().cloned())
+                .expect("Batch output file to append to should be a CSV with a header")
+                .iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        });
+        let (file, path) = output::open(path, mode);
+        let mut csv_writer = Writer::from_writer(file);
+
+        // Rows appended to an existing CSV reuse its header
+        let columns = match file_header {
+            Some(file_header) => {
+                let columns = output::csv_columns(&file_header, &header);
+                let left_out = header.len() - columns.iter().flatten().count();
+
+                if left_out > 0 {
+                    println!(
+                        "{left_out} columns aren't in the header of {path} and are left out",
+                        left_out = left_out,
+                        path = path
+                    );
+                }
+
+                columns
+            }
+            None => {
+                csv_writer
+                    .write_record(&header)
+                    .expect("Batch output file should be writable");
+
+                (0..header.len()).map(Some).collect()
+            }
+        };
+
+        BatchPrinter::Csv {
+            csv_writer: Box::new(csv_writer),
+            metric_names,
+            columns,
         }
     }
 
-    pub fn write_line(&mut self, row: Row) -> Result<(), Box<dyn Error>> {
-        self.csv_writer.serialize(row)?;
+    pub fn write_row(&mut self, row: &BatchRow) {
+        match self {
+            BatchPrinter::Csv {
+                csv_writer,
+                metric_names,
+                columns,
+            } => {
+                let record = batch_csv_record(row, metric_names);
 
-        Ok(())
+                csv_writer
+                    .write_record(columns.iter().map(|column| match column {
+                        Some(index) => record[*index].as_str(),
+                        None => "",
+                    }))
+                    .expect("Batch output file should be writable");
+                csv_writer
+                    .flush()
+                    .expect("Batch output file should be writable");
+            }
+            BatchPrinter::Json { rows, .. } => rows.push(serde_json::json!({
+                "url": row.entry.url,
+                "label": row.entry.label,
+                "group": row.entry.group,
+                "desktop": batch_strategy_json(&row.desktop),
+                "mobile": batch_strategy_json(&row.mobile),
+            })),
+        }
     }
 
-    pub fn flush(&mut self) -> io::Result<()> {
-        self.csv_writer.flush()
+    pub fn finish(&mut self) {
+        if let BatchPrinter::Json { path, rows } = self {
+            let content = serde_json::to_string_pretty(rows).unwrap();
+
+            fs::write(path, content).expect("Batch output file should be writable");
+        }
+    }
+}
+
+#[cfg(test)]
+mod printer_tests {
+    use crate::batch_input::BatchEntry;
+    use crate::{metrics, BatchRow, BatchStrategyResult, PSIResultValues};
+
+    #[test]
+    fn batch_csv_record() {
+        let metrics = metrics::builtin_metrics();
+        let metric_names = metrics
+            .iter()
+            .map(|metric| metric.name.clone())
+            .collect::<Vec<_>>();
+        let values = PSIResultValues::from_metrics(&metrics, |_| vec![1_f64, 2_f64, 3_f64]);
+        let row = BatchRow {
+            entry: BatchEntry::new("https://a.example/".to_string()),
+            mobile: Some(BatchStrategyResult {
+                success_runs: 3,
+                failed_runs: Vec::new(),
+                statistics: crate::statistics::calculate_statistics(&values, 0.95),
+                values,
+            }),
+            desktop: None,
+        };
+
+        let header = super::batch_csv_header(&metric_names);
+        let record = super::batch_csv_record(&row, &metric_names);
+        let column =
+            |name: &str| record[header.iter().position(|column| column == name).unwrap()].as_str();
+
+        assert_eq!(header.len(), record.len());
+        assert_eq!(
+            vec![
+                "Store",
+                "Desktop - Mean",
+                "Desktop - Median",
+                "Mobile - Mean",
+                "Mobile - Median"
+            ],
+            header[..5]
+        );
+        assert_eq!("2", column("Mobile - Median"));
+        assert_eq!("1", column("Mobile - largest_contentful_paint - Min"));
+        assert_eq!("3", column("Mobile - largest_contentful_paint - Max"));
+        assert_eq!("not tested", column("Desktop - Failure"));
     }
 }
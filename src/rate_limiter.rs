@@ -0,0 +1,78 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::time::sleep;
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token bucket limiting how many requests per second are sent to the PSI API. The bucket holds
+/// up to one second of requests, so short bursts are allowed but the average rate is `qps`.
+pub struct RateLimiter {
+    qps: f64,
+    capacity: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(qps: f64) -> RateLimiter {
+        let capacity = qps.max(1_f64);
+
+        RateLimiter {
+            qps,
+            capacity,
+            bucket: Mutex::new(Bucket {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token from the bucket and returns how long the caller must wait before using it.
+    /// The bucket may go negative, which queues callers in the order they reserved.
+    fn reserve(&self) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+
+        bucket.tokens = (bucket.tokens + elapsed * self.qps).min(self.capacity);
+        bucket.last_refill = now;
+        bucket.tokens -= 1_f64;
+
+        if bucket.tokens >= 0_f64 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.qps)
+        }
+    }
+
+    /// Waits until a request can be sent without going over the rate.
+    pub async fn acquire(&self) {
+        let wait = self.reserve();
+
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod rate_limiter_tests {
+    use std::time::Duration;
+
+    #[test]
+    fn reserve() {
+        let rate_limiter = super::RateLimiter::new(2_f64);
+
+        assert_eq!(Duration::ZERO, rate_limiter.reserve());
+        assert_eq!(Duration::ZERO, rate_limiter.reserve());
+
+        let wait = rate_limiter.reserve();
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+
+        let wait = rate_limiter.reserve();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_millis(1000));
+    }
+}
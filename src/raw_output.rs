@@ -0,0 +1,174 @@
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::sync::Mutex;
+
+use crate::batch_input::BatchEntry;
+use crate::outcome::RunError;
+use crate::tester::PageAudits;
+use crate::{PSIStatisticResult, Strategy};
+
+/// A run of a page test, a line of the raw output.
+#[derive(Debug, Serialize)]
+struct RawRun<'a> {
+    url: &'a str,
+    /// Label and group of the batch entry of the page, `null` for single pages and entries
+    /// without them.
+    label: Option<&'a str>,
+    group: Option<&'a str>,
+    strategy: String,
+    /// Index of the run in the test of the page, failed runs included.
+    run: usize,
+    /// 0 for the test of the page, 1 and 2 for the retests of its failed runs in batch mode. A
+    /// retested run has a line for each attempt, the one with the highest attempt is its final
+    /// outcome.
+    attempt: usize,
+    success: bool,
+    analysis_utc_timestamp: Option<&'a str>,
+    fetch_time: Option<&'a str>,
+    lighthouse_version: Option<&'a str>,
+    final_url: Option<&'a str>,
+    /// Value of every metric, `null` when the run doesn't have it. `null` for failed runs.
+    metrics: Option<PSIStatisticResult<Option<f64>>>,
+    error: Option<&'a RunError>,
+}
+
+/// JSON Lines file receiving every run of every tested page, as they finish.
+pub struct RawOutput {
+    file: Mutex<File>,
+}
+
+impl RawOutput {
+    pub fn create(path: &str) -> RawOutput {
+        RawOutput {
+            file: Mutex::new(File::create(path).expect("Raw output file should be writable")),
+        }
+    }
+
+    pub fn write_runs(
+        &self,
+        url: &str,
+        strategy: Strategy,
+        page_audits: &PageAudits,
+        entry: Option<&BatchEntry>,
+    ) {
+        let runs = (0..page_audits.runs.len()).collect::<Vec<_>>();
+
+        self.write_lines(&raw_runs(url, strategy, page_audits, &runs, 0, entry));
+    }
+
+    /// Writes the runs of a page retested after failing, with their index in the test of the
+    /// page and the retest `attempt`, starting at 1.
+    pub fn write_retested_runs(
+        &self,
+        url: &str,
+        strategy: Strategy,
+        page_audits: &PageAudits,
+        runs: &[usize],
+        attempt: usize,
+        entry: Option<&BatchEntry>,
+    ) {
+        self.write_lines(&raw_runs(url, strategy, page_audits, runs, attempt, entry));
+    }
+
+    fn write_lines(&self, raw_runs: &[RawRun]) {
+        let lines = raw_runs
+            .iter()
+            .map(|raw_run| serde_json::to_string(raw_run).unwrap() + "\n")
+            .collect::<String>();
+        // The lines of a page are written at once, pages tested concurrently don't interleave
+        let mut file = self.file.lock().unwrap();
+
+        file.write_all(lines.as_bytes())
+            .expect("Raw output file should be writable");
+    }
+}
+
+fn raw_runs<'a>(
+    url: &'a str,
+    strategy: Strategy,
+    page_audits: &'a PageAudits,
+    runs: &[usize],
+    attempt: usize,
+    entry: Option<&'a BatchEntry>,
+) -> Vec<RawRun<'a>> {
+    runs.iter()
+        .map(|&run| {
+            let outcome = &page_audits.runs[run];
+            let response = outcome.response();
+            let result = outcome.result();
+
+            RawRun {
+                url,
+                label: entry.and_then(|entry| entry.label.as_deref()),
+                group: entry.and_then(|entry| entry.group.as_deref()),
+                strategy: strategy.to_string(),
+                run,
+                attempt,
+                success: response.is_some(),
+                analysis_utc_timestamp: response
+                    .and_then(|response| response.analysis_utc_timestamp.as_deref()),
+                fetch_time: result.and_then(|result| result.fetch_time.as_deref()),
+                lighthouse_version: result.and_then(|result| result.lighthouse_version.as_deref()),
+                final_url: result.and_then(|result| result.final_url.as_deref()),
+                metrics: result.map(|result| {
+                    PSIStatisticResult::from_metrics(&page_audits.metrics, |metric| {
+                        metric.value(result)
+                    })
+                }),
+                error: outcome.error(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod raw_output_tests {
+    use crate::batch_input::BatchEntry;
+    use crate::outcome::{RunError, RunOutcome};
+    use crate::tester::PageAudits;
+    use crate::Strategy;
+
+    #[test]
+    fn raw_runs() {
+        let response = crate::tester::parse_response(include_str!("../res.json")).unwrap();
+        let page_audits = PageAudits {
+            runs: vec![
+                RunOutcome::Failure(RunError::Timeout),
+                RunOutcome::Success(Box::new(response)),
+            ],
+            metrics: crate::metrics::builtin_metrics(),
+        };
+
+        let mut entry = BatchEntry::new("https://base.vtex.app/".to_string());
+        entry.label = Some("Home".to_string());
+        entry.group = Some("home".to_string());
+
+        let raw_runs = super::raw_runs(
+            "https://base.vtex.app/",
+            Strategy::MOBILE,
+            &page_audits,
+            &[0, 1],
+            0,
+            Some(&entry),
+        );
+        let failed = serde_json::to_value(&raw_runs[0]).unwrap();
+        let success = serde_json::to_value(&raw_runs[1]).unwrap();
+
+        assert_eq!(false, failed["success"]);
+        assert_eq!("timeout", failed["error"]["kind"]);
+        assert!(failed["metrics"].is_null());
+        assert_eq!(1, success["run"]);
+        assert_eq!(0, success["attempt"]);
+        assert_eq!("Home", success["label"]);
+        assert_eq!("home", failed["group"]);
+        assert_eq!("mobile", success["strategy"]);
+        assert_eq!(
+            "2022-03-07T14:36:23.924Z",
+            success["analysis_utc_timestamp"]
+        );
+        assert_eq!("9.3.0", success["lighthouse_version"]);
+        assert_eq!(0.93, success["metrics"]["score"]);
+        assert!(success["error"].is_null());
+    }
+}
@@ -0,0 +1,228 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::statistics::percentiles;
+use crate::tester::without_cache_buster;
+use crate::LHResult;
+
+const RESOURCE_SUMMARY_AUDIT: &str = "resource-summary";
+const NETWORK_REQUESTS_AUDIT: &str = "network-requests";
+/// Resource types reported, in the order of the output table.
+const RESOURCE_TYPES: [&str; 9] = [
+    "total",
+    "document",
+    "script",
+    "stylesheet",
+    "image",
+    "font",
+    "media",
+    "other",
+    "third-party",
+];
+/// Transfer sizes vary a little between runs because of compression and headers, they're only
+/// flagged when the range exceeds this fraction of the median.
+const TRANSFER_SIZE_TOLERANCE: f64 = 0.05_f64;
+/// Unstable requests reported.
+const MAX_UNSTABLE_REQUESTS: usize = 20;
+
+/// Request count and transfer size of a resource type across runs.
+#[derive(Debug, Serialize)]
+pub struct ResourceSummary {
+    pub resource_type: String,
+    pub median_requests: f64,
+    pub min_requests: f64,
+    pub max_requests: f64,
+    pub median_transfer_size: f64,
+    pub min_transfer_size: f64,
+    pub max_transfer_size: f64,
+    /// The request count or the transfer size changed between runs.
+    pub varies: bool,
+}
+
+/// A request missing from some runs or failing in some runs, e.g. an A/B-tested bundle or a
+/// flaky CDN.
+#[derive(Debug, Serialize)]
+pub struct UnstableRequest {
+    pub url: String,
+    pub resource_type: Option<String>,
+    /// Runs in which the page made the request.
+    pub runs: usize,
+    /// Runs in which the request failed or got an HTTP error.
+    pub runs_failed: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResourceReport {
+    pub runs: usize,
+    pub resources: Vec<ResourceSummary>,
+    pub unstable_requests: Vec<UnstableRequest>,
+}
+
+fn audit_items<'a>(result: &'a LHResult, audit_id: &str) -> &'a [serde_json::Value] {
+    result
+        .audits
+        .get(audit_id)
+        .and_then(|audit| audit.details.as_ref())
+        .map(|details| details.items.as_slice())
+        .unwrap_or_default()
+}
+
+fn number(item: &serde_json::Value, key: &str) -> f64 {
+    item.get(key)
+        .and_then(serde_json::Value::as_f64)
+        .unwrap_or_default()
+}
+
+fn summarize(resource_type: &str, lh_results: &[&LHResult]) -> ResourceSummary {
+    let (requests, transfer_sizes): (Vec<_>, Vec<_>) = lh_results
+        .iter()
+        .map(|result| {
+            audit_items(result, RESOURCE_SUMMARY_AUDIT)
+                .iter()
+                .find(|item| {
+                    item.get("resourceType").and_then(serde_json::Value::as_str)
+                        == Some(resource_type)
+                })
+                .map_or((0_f64, 0_f64), |item| {
+                    (number(item, "requestCount"), number(item, "transferSize"))
+                })
+        })
+        .unzip();
+    let requests = percentiles(&requests);
+    let transfer_sizes = percentiles(&transfer_sizes);
+
+    ResourceSummary {
+        resource_type: resource_type.to_string(),
+        varies: requests.min != requests.max
+            || transfer_sizes.max - transfer_sizes.min
+                > transfer_sizes.median * TRANSFER_SIZE_TOLERANCE,
+        median_requests: requests.median,
+        min_requests: requests.min,
+        max_requests: requests.max,
+        median_transfer_size: transfer_sizes.median,
+        min_transfer_size: transfer_sizes.min,
+        max_transfer_size: transfer_sizes.max,
+    }
+}
+
+/// Failed requests have no status code or finished flag set to false.
+fn is_failed(item: &serde_json::Value) -> bool {
+    let status_code = number(item, "statusCode");
+    let finished = item
+        .get("finished")
+        .and_then(serde_json::Value::as_bool)
+        .unwrap_or(true);
+
+    !finished || status_code == 0_f64 || status_code >= 400_f64
+}
+
+fn unstable_requests(lh_results: &[&LHResult]) -> Vec<UnstableRequest> {
+    // URL -> request of every run it was made in
+    let mut requests: HashMap<String, UnstableRequest> = HashMap::new();
+
+    for result in lh_results {
+        let mut seen = Vec::new();
+
+        for item in audit_items(result, NETWORK_REQUESTS_AUDIT) {
+            // The page document has a different cache buster in every run
+            let url = match item.get("url").and_then(serde_json::Value::as_str) {
+                Some(url) => without_cache_buster(url),
+                None => continue,
+            };
+            let request = requests
+                .entry(url.clone())
+                .or_insert_with(|| UnstableRequest {
+                    url: url.clone(),
+                    resource_type: item
+                        .get("resourceType")
+                        .and_then(serde_json::Value::as_str)
+                        .map(str::to_string),
+                    runs: 0,
+                    runs_failed: 0,
+                });
+
+            // A URL requested twice in the same run counts once
+            if seen.contains(&url) {
+                continue;
+            }
+            seen.push(url);
+            request.runs += 1;
+            if is_failed(item) {
+                request.runs_failed += 1;
+            }
+        }
+    }
+
+    let mut unstable_requests = requests
+        .into_values()
+        .filter(|request| request.runs < lh_results.len() || request.runs_failed > 0)
+        .collect::<Vec<_>>();
+    unstable_requests.sort_by(|a, b| {
+        b.runs_failed
+            .cmp(&a.runs_failed)
+            .then(a.runs.cmp(&b.runs))
+            .then(a.url.cmp(&b.url))
+    });
+    unstable_requests.truncate(MAX_UNSTABLE_REQUESTS);
+
+    unstable_requests
+}
+
+/// Request counts and transfer sizes per resource type, and the requests that change between
+/// runs.
+pub fn aggregate(lh_results: &[&LHResult]) -> ResourceReport {
+    ResourceReport {
+        runs: lh_results.len(),
+        resources: RESOURCE_TYPES
+            .iter()
+            .map(|resource_type| summarize(resource_type, lh_results))
+            .collect(),
+        unstable_requests: unstable_requests(lh_results),
+    }
+}
+
+#[cfg(test)]
+mod resources_tests {
+    #[test]
+    fn aggregate() {
+        let mut response =
+            serde_json::from_str::<serde_json::Value>(include_str!("../res.json")).unwrap();
+        // Every run requests the page with its own cache buster
+        response["lighthouseResult"]["audits"]["network-requests"]["details"]["items"][0]["url"] =
+            "https://base.vtex.app/?__v=1".into();
+        let stable = crate::tester::parse_response(&response.to_string())
+            .unwrap()
+            .lighthouse_result;
+
+        let mut changed = response.clone();
+        let audits = &mut changed["lighthouseResult"]["audits"];
+        audits["network-requests"]["details"]["items"][0]["url"] =
+            "https://base.vtex.app/?__v=2".into();
+        audits["resource-summary"]["details"]["items"][1]["requestCount"] = 7.into();
+        audits["network-requests"]["details"]["items"][1]["statusCode"] = 503.into();
+        audits["network-requests"]["details"]["items"]
+            .as_array_mut()
+            .unwrap()
+            .push(serde_json::json!({"url": "https://cdn.example/variant-b.js", "resourceType": "Script", "statusCode": 200}));
+        let changed = crate::tester::parse_response(&changed.to_string())
+            .unwrap()
+            .lighthouse_result;
+
+        let report = super::aggregate(&[&stable, &changed, &stable]);
+        let script = &report.resources[2];
+
+        assert_eq!("script", script.resource_type);
+        assert_eq!(6_f64, script.median_requests);
+        assert_eq!(7_f64, script.max_requests);
+        assert!(script.varies);
+        assert!(!report.resources[1].varies);
+
+        assert_eq!(2, report.unstable_requests.len());
+        assert_eq!(1, report.unstable_requests[0].runs_failed);
+        assert_eq!(
+            "https://cdn.example/variant-b.js",
+            report.unstable_requests[1].url
+        );
+        assert_eq!(1, report.unstable_requests[1].runs);
+    }
+}
@@ -0,0 +1,154 @@
+use reqwest::StatusCode;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::outcome::{RunError, RunOutcome};
+use crate::tester::parse_response;
+use crate::Strategy;
+
+/// Directory of the saved responses of a page and strategy, `<dir>/<page>/<strategy>`. The page
+/// URL is made filesystem safe by percent-encoding every byte but ASCII letters, digits, `-`,
+/// `_` and `.`, so different URLs never share a directory.
+fn page_dir(dir: &str, url: &str, strategy: Strategy) -> PathBuf {
+    let page = url
+        .bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}", byte = byte),
+        })
+        .collect::<String>();
+
+    Path::new(dir).join(page).join(strategy.to_string())
+}
+
+/// Path of a saved response. `batch` identifies the requests made together, e.g. a wave of
+/// adaptive sampling, so saving again to the same directory never overwrites a response.
+pub fn response_path(dir: &str, url: &str, strategy: Strategy, batch: &str, run: usize) -> PathBuf {
+    page_dir(dir, url, strategy).join(format!("{batch}-{run:03}.json", batch = batch, run = run))
+}
+
+/// Saves the raw body of a PSI response. Error responses have their status before the
+/// extension, e.g. `<batch>-<run>.429.json`.
+pub fn save(path: &Path, status: StatusCode, body: &str) {
+    let path = match status.is_success() {
+        true => path.to_path_buf(),
+        false => path.with_extension(format!("{status}.json", status = status.as_u16())),
+    };
+
+    fs::create_dir_all(path.parent().unwrap()).expect("Responses directory should be writable");
+    fs::write(path, body).expect("Responses directory should be writable");
+}
+
+/// Status of an error response saved by `save`, `None` for a success one.
+fn saved_status(path: &Path) -> Option<StatusCode> {
+    Path::new(path.file_stem()?)
+        .extension()?
+        .to_str()?
+        .parse::<u16>()
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+}
+
+/// Runs of the saved responses of a page and strategy, in the order they were saved. An error
+/// when nothing was saved for them, e.g. a wrong directory.
+pub fn replay(dir: &str, url: &str, strategy: Strategy) -> Result<Vec<RunOutcome>, String> {
+    let mut paths = match fs::read_dir(page_dir(dir, url, strategy)) {
+        Ok(entries) => entries
+            .map(|entry| {
+                entry
+                    .expect("Responses directory should be readable")
+                    .path()
+            })
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+
+    if paths.is_empty() {
+        return Err(format!(
+            "No saved responses of {url} ({strategy}) in {dir}",
+            url = url,
+            strategy = strategy,
+            dir = dir
+        ));
+    }
+
+    Ok(paths
+        .iter()
+        .map(|path| {
+            let body = fs::read_to_string(path).expect("Saved response should be readable");
+
+            if let Some(status) = saved_status(path) {
+                return RunOutcome::Failure(RunError::from_response(status, &body));
+            }
+
+            match parse_response(&body) {
+                Ok(response) => RunOutcome::Success(Box::new(response)),
+                Err(error) => RunOutcome::Failure(error),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod responses_tests {
+    use crate::outcome::RunError;
+    use crate::Strategy;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn page_dir() {
+        assert_eq!(
+            std::path::Path::new("responses/https%3A%2F%2Fa.example%2F%3Fa%3D1/mobile"),
+            super::page_dir("responses", "https://a.example/?a=1", Strategy::MOBILE)
+        );
+        assert_ne!(
+            super::page_dir("responses", "https://a.example/a-b", Strategy::MOBILE),
+            super::page_dir("responses", "https://a.example/a/b", Strategy::MOBILE)
+        );
+    }
+
+    #[test]
+    fn save_and_replay() {
+        let dir = std::env::temp_dir().join(format!("psi-test-responses-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let url = "https://base.vtex.app/?a=1";
+
+        for run in [1, 0] {
+            super::save(
+                &super::response_path(dir, url, Strategy::MOBILE, "1646663783924", run),
+                StatusCode::OK,
+                include_str!("../res.json"),
+            );
+        }
+        super::save(
+            &super::response_path(dir, url, Strategy::MOBILE, "1646663783925", 0),
+            StatusCode::OK,
+            r#"{"lighthouseResult": {"runtimeError": {"code": "NO_FCP", "message": "No FCP"}}}"#,
+        );
+        super::save(
+            &super::response_path(dir, url, Strategy::MOBILE, "1646663783925", 1),
+            StatusCode::TOO_MANY_REQUESTS,
+            r#"{"error": {"code": 429, "message": "Quota exceeded", "status": "RESOURCE_EXHAUSTED"}}"#,
+        );
+
+        let runs = super::replay(dir, url, Strategy::MOBILE).unwrap();
+
+        assert_eq!(4, runs.len());
+        assert!(runs[0].result().is_some());
+        assert!(runs[2].error().is_some());
+        assert!(matches!(
+            runs[3].error(),
+            Some(RunError::QuotaExceeded { .. })
+        ));
+        assert!(super::replay(dir, url, Strategy::DESKTOP).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
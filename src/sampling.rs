@@ -0,0 +1,171 @@
+use serde::Serialize;
+
+use crate::batch_input::BatchEntry;
+use crate::statistics;
+use crate::tester::{self, PageAudits, Tester};
+use crate::Strategy;
+
+/// Runs added on each wave after the first one.
+const WAVE_SIZE: i8 = 5;
+
+pub struct AdaptiveSampling {
+    /// Metric whose confidence interval decides when to stop, e.g. score.
+    pub metric: String,
+    /// Confidence interval half-width to reach.
+    pub target_half_width: f64,
+    pub confidence_level: f64,
+    pub min_runs: i8,
+    pub max_runs: i8,
+}
+
+/// How many runs are made for each page and strategy.
+pub enum Sampling {
+    /// Always the same number of runs.
+    Fixed(i8),
+    /// Runs in waves until the confidence interval is tight enough.
+    Adaptive(AdaptiveSampling),
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdaptiveReport {
+    pub metric: String,
+    pub half_width: f64,
+    pub target_half_width: f64,
+    pub target_reached: bool,
+    pub runs: usize,
+}
+
+/// Confidence interval half-width of `metric` with the successful runs so far.
+fn half_width(page_audits: &PageAudits, metric: &str, confidence_level: f64) -> f64 {
+    let values = page_audits.values();
+    let data = values.metric(metric).expect("Metric validated on CLI");
+
+    if data.len() < 2 {
+        return f64::INFINITY;
+    }
+
+    let mean = statistics::mean(data);
+    let std_deviation = statistics::std_deviation(data, mean);
+    let (ci_min, ci_max) =
+        statistics::confidence_interval(mean, std_deviation, data.len(), confidence_level);
+
+    (ci_max - ci_min) / 2_f64
+}
+
+/// More waves won't help when the last one had no successful run, or when Lighthouse couldn't
+/// audit the page.
+fn is_stalled(wave: &PageAudits) -> bool {
+    wave.success_runs() == 0
+        || wave
+            .failures()
+            .iter()
+            .any(|(_, error)| !tester::is_retryable(error))
+}
+
+async fn sample_adaptive(
+    tester: &Tester,
+    url: &str,
+    adaptive: &AdaptiveSampling,
+    strategy: Strategy,
+) -> (PageAudits, AdaptiveReport) {
+    let mut page_audits = tester
+        .get_page_audits(url, adaptive.min_runs, strategy)
+        .await;
+    let mut stalled = is_stalled(&page_audits);
+
+    loop {
+        let half_width = half_width(&page_audits, &adaptive.metric, adaptive.confidence_level);
+        let runs = page_audits.runs.len();
+        let target_reached = half_width <= adaptive.target_half_width;
+
+        if target_reached || stalled || runs >= adaptive.max_runs as usize {
+            let report = AdaptiveReport {
+                metric: adaptive.metric.clone(),
+                half_width,
+                target_half_width: adaptive.target_half_width,
+                target_reached,
+                runs,
+            };
+
+            return (page_audits, report);
+        }
+
+        let wave_size = WAVE_SIZE.min(adaptive.max_runs - runs as i8);
+        let wave = tester.get_page_audits(url, wave_size, strategy).await;
+
+        stalled = is_stalled(&wave);
+        page_audits.runs.extend(wave.runs);
+    }
+}
+
+/// Tests the page with the given sampling. The adaptive report is only returned for adaptive
+/// sampling. Every run is written to the raw output of the tester, if any, with the label and
+/// group of the batch entry of the page.
+pub async fn sample_page(
+    tester: &Tester,
+    url: &str,
+    sampling: &Sampling,
+    strategy: Strategy,
+    entry: Option<&BatchEntry>,
+) -> (PageAudits, Option<AdaptiveReport>) {
+    let (page_audits, adaptive_report) = match (tester.replay_page_audits(url, strategy), sampling)
+    {
+        // Every saved run is replayed, whatever the sampling
+        (Some(page_audits), _) => (page_audits, None),
+        (None, Sampling::Fixed(number_of_runs)) => (
+            tester.get_page_audits(url, *number_of_runs, strategy).await,
+            None,
+        ),
+        (None, Sampling::Adaptive(adaptive)) => {
+            let (page_audits, report) = sample_adaptive(tester, url, adaptive, strategy).await;
+
+            (page_audits, Some(report))
+        }
+    };
+
+    if let Some(raw_output) = tester.raw_output() {
+        raw_output.write_runs(url, strategy, &page_audits, entry);
+    }
+
+    (page_audits, adaptive_report)
+}
+
+#[cfg(test)]
+mod sampling_tests {
+    use crate::outcome::{RunError, RunOutcome};
+    use crate::tester::PageAudits;
+
+    fn wave(runs: Vec<RunOutcome>) -> PageAudits {
+        PageAudits {
+            runs,
+            metrics: crate::metrics::builtin_metrics(),
+        }
+    }
+
+    fn success() -> RunOutcome {
+        let response = crate::tester::parse_response(include_str!("../res.json")).unwrap();
+
+        RunOutcome::Success(Box::new(response))
+    }
+
+    #[test]
+    fn is_stalled() {
+        let lighthouse_error = RunError::Lighthouse {
+            code: "NO_FCP".to_string(),
+            message: "The page did not paint any content".to_string(),
+        };
+
+        assert!(!super::is_stalled(&wave(vec![
+            success(),
+            RunOutcome::Failure(RunError::Timeout)
+        ])));
+        assert!(super::is_stalled(&wave(vec![
+            RunOutcome::Failure(RunError::Timeout),
+            RunOutcome::Failure(RunError::Timeout)
+        ])));
+        assert!(super::is_stalled(&wave(vec![
+            success(),
+            RunOutcome::Failure(lighthouse_error)
+        ])));
+    }
+}
@@ -1,166 +1,487 @@
+use serde::Serialize;
+
 use crate::{PSIResultValues, PSIStatisticResult};
 
-const Z_VALUE: f64 = 1.96_f64; // z-value for 95% confidence level.
+pub const DEFAULT_CONFIDENCE_LEVEL: f64 = 0.95_f64;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Percentiles {
+    pub min: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub max: f64,
+}
 
-pub fn mean(results: &[f64], number_of_runs: i8) -> f64 {
-    return results.iter().sum::<f64>() / number_of_runs as f64;
+/// Every statistic reported for a page sample.
+pub struct PageStatistics {
+    pub mean: PSIStatisticResult<f64>,
+    pub variance: PSIStatisticResult<f64>,
+    pub std_deviation: PSIStatisticResult<f64>,
+    pub confidence_interval: PSIStatisticResult<(f64, f64)>,
+    pub percentiles: PSIStatisticResult<Percentiles>,
 }
 
-pub fn calculate_mean(
+pub fn calculate_statistics(
     page_results: &PSIResultValues,
-    number_of_runs: i8,
-) -> PSIStatisticResult<f64> {
-    PSIStatisticResult {
-        cumulative_layout_shift: mean(&page_results.cumulative_layout_shift, number_of_runs),
-        first_contentful_paint: mean(&page_results.first_contentful_paint, number_of_runs),
-        js_execution_time: mean(&page_results.js_execution_time, number_of_runs),
-        largest_contentful_paint: mean(&page_results.largest_contentful_paint, number_of_runs),
-        speed_index: mean(&page_results.speed_index, number_of_runs),
-        time_to_interactive: mean(&page_results.time_to_interactive, number_of_runs),
-        total_blocking_time: mean(&page_results.total_blocking_time, number_of_runs),
-        score: mean(&page_results.score, number_of_runs),
+    confidence_level: f64,
+) -> PageStatistics {
+    let mean = calculate_mean(page_results);
+    let variance = calculate_variance(page_results, &mean);
+    let std_deviation = calculate_deviation(&variance);
+    let confidence_interval =
+        calculate_confidence_interval(page_results, &mean, &std_deviation, confidence_level);
+
+    PageStatistics {
+        mean,
+        variance,
+        std_deviation,
+        confidence_interval,
+        percentiles: calculate_percentiles(page_results),
     }
 }
 
-pub fn std_deviation(data: &[f64], mean: f64, number_of_runs: i8) -> f64 {
-    return data
-        .iter()
+pub fn mean(results: &[f64]) -> f64 {
+    results.iter().sum::<f64>() / results.len() as f64
+}
+
+pub fn calculate_mean(page_results: &PSIResultValues) -> PSIStatisticResult<f64> {
+    page_results.map(|values| mean(values))
+}
+
+/// Sample variance, dividing by N - 1. It isn't defined (NaN) with less than two values.
+pub fn variance(data: &[f64], mean: f64) -> f64 {
+    if data.len() < 2 {
+        return f64::NAN;
+    }
+
+    data.iter()
         .map(|value| {
             let diff = mean - value;
 
             diff * diff
         })
         .sum::<f64>()
-        / number_of_runs as f64;
+        / (data.len() as f64 - 1_f64)
 }
 
-pub fn calculate_deviation(
+/// Sample standard deviation, the square root of the sample variance.
+pub fn std_deviation(data: &[f64], mean: f64) -> f64 {
+    variance(data, mean).sqrt()
+}
+
+pub fn calculate_variance(
     page_results: &PSIResultValues,
     page_mean: &PSIStatisticResult<f64>,
-    number_of_runs: i8,
 ) -> PSIStatisticResult<f64> {
-    PSIStatisticResult {
-        cumulative_layout_shift: std_deviation(
-            &page_results.cumulative_layout_shift,
-            page_mean.cumulative_layout_shift,
-            number_of_runs,
-        ),
-        first_contentful_paint: std_deviation(
-            &page_results.first_contentful_paint,
-            page_mean.first_contentful_paint,
-            number_of_runs,
-        ),
-        js_execution_time: std_deviation(
-            &page_results.js_execution_time,
-            page_mean.js_execution_time,
-            number_of_runs,
-        ),
-        largest_contentful_paint: std_deviation(
-            &page_results.largest_contentful_paint,
-            page_mean.largest_contentful_paint,
-            number_of_runs,
-        ),
-        speed_index: std_deviation(
-            &page_results.speed_index,
-            page_mean.speed_index,
-            number_of_runs,
-        ),
-        time_to_interactive: std_deviation(
-            &page_results.time_to_interactive,
-            page_mean.time_to_interactive,
-            number_of_runs,
-        ),
-        total_blocking_time: std_deviation(
-            &page_results.total_blocking_time,
-            page_mean.total_blocking_time,
-            number_of_runs,
-        ),
-        score: std_deviation(&page_results.score, page_mean.score, number_of_runs),
-    }
-}
-
-// Reference: https://www.dummies.com/article/academics-the-arts/math/statistics/how-to-calculate-a-confidence-interval-for-a-population-mean-when-you-know-its-standard-deviation-169722/
-pub fn confidence_interval(mean: f64, std_deviation: f64, number_of_runs: i8) -> (f64, f64) {
-    // margin error =  z value * std_deviation / sqrt (number_of_runs)
-    let margin_error = Z_VALUE * (std_deviation / (number_of_runs as f64).sqrt());
+    page_results.map_metrics(|name, values| variance(values, *page_mean.metric(name).unwrap()))
+}
+
+pub fn calculate_deviation(page_variance: &PSIStatisticResult<f64>) -> PSIStatisticResult<f64> {
+    page_variance.map(|variance| variance.sqrt())
+}
+
+/// Natural logarithm of the gamma function, Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |series, (index, coefficient)| {
+            series + coefficient / (x + 1_f64 + index as f64)
+        });
+
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Continued fraction of the incomplete beta function, modified Lentz's method.
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 300;
+    const EPSILON: f64 = 1e-15;
+    const MIN_VALUE: f64 = 1e-300;
+
+    let qab = a + b;
+    let qap = a + 1_f64;
+    let qam = a - 1_f64;
+    let mut c = 1_f64;
+    let mut d = 1_f64 - qab * x / qap;
+    if d.abs() < MIN_VALUE {
+        d = MIN_VALUE;
+    }
+    d = 1_f64 / d;
+    let mut h = d;
+
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        let m2 = 2_f64 * m;
+
+        let aa = m * (b - m) * x / ((qam + m2) * (a + m2));
+        d = 1_f64 + aa * d;
+        if d.abs() < MIN_VALUE {
+            d = MIN_VALUE;
+        }
+        c = 1_f64 + aa / c;
+        if c.abs() < MIN_VALUE {
+            c = MIN_VALUE;
+        }
+        d = 1_f64 / d;
+        h *= d * c;
+
+        let aa = -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2));
+        d = 1_f64 + aa * d;
+        if d.abs() < MIN_VALUE {
+            d = MIN_VALUE;
+        }
+        c = 1_f64 + aa / c;
+        if c.abs() < MIN_VALUE {
+            c = MIN_VALUE;
+        }
+        d = 1_f64 / d;
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1_f64).abs() < EPSILON {
+            break;
+        }
+    }
+
+    h
+}
+
+/// Regularized incomplete beta function I_x(a, b).
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0_f64 {
+        return 0_f64;
+    }
+    if x >= 1_f64 {
+        return 1_f64;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1_f64 - x).ln()).exp();
+
+    if x < (a + 1_f64) / (a + b + 2_f64) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1_f64 - front * beta_continued_fraction(b, a, 1_f64 - x) / b
+    }
+}
+
+/// Cumulative distribution function of the Student's t-distribution.
+pub fn student_t_cdf(t: f64, degrees_of_freedom: f64) -> f64 {
+    let x = degrees_of_freedom / (degrees_of_freedom + t * t);
+    let tail = 0.5 * incomplete_beta(degrees_of_freedom / 2_f64, 0.5, x);
+
+    if t > 0_f64 {
+        1_f64 - tail
+    } else {
+        tail
+    }
+}
+
+/// Inverse of the Student's t-distribution cdf, found by bisection.
+pub fn student_t_quantile(probability: f64, degrees_of_freedom: f64) -> f64 {
+    let mut low = -1e4_f64;
+    let mut high = 1e4_f64;
+
+    for _ in 0..200 {
+        let middle = (low + high) / 2_f64;
+
+        if student_t_cdf(middle, degrees_of_freedom) < probability {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    (low + high) / 2_f64
+}
+
+// Reference: https://www.itl.nist.gov/div898/handbook/eda/section3/eda352.htm
+pub fn confidence_interval(
+    mean: f64,
+    std_deviation: f64,
+    number_of_runs: usize,
+    confidence_level: f64,
+) -> (f64, f64) {
+    if number_of_runs < 2 {
+        return (f64::NAN, f64::NAN);
+    }
+
+    // Two-sided critical value with N - 1 degrees of freedom
+    let t_value = student_t_quantile(
+        (1_f64 + confidence_level) / 2_f64,
+        (number_of_runs - 1) as f64,
+    );
+    // margin error =  t value * std_deviation / sqrt (number_of_runs)
+    let margin_error = t_value * (std_deviation / (number_of_runs as f64).sqrt());
 
     (mean - margin_error, mean + margin_error)
 }
 
 pub fn calculate_confidence_interval(
+    page_results: &PSIResultValues,
     mean: &PSIStatisticResult<f64>,
     std_deviation: &PSIStatisticResult<f64>,
-    number_of_runs: i8,
+    confidence_level: f64,
 ) -> PSIStatisticResult<(f64, f64)> {
-    PSIStatisticResult::<(f64, f64)> {
-        cumulative_layout_shift: confidence_interval(
-            mean.cumulative_layout_shift,
-            std_deviation.cumulative_layout_shift,
-            number_of_runs,
-        ),
-        first_contentful_paint: confidence_interval(
-            mean.first_contentful_paint,
-            std_deviation.first_contentful_paint,
-            number_of_runs,
-        ),
-        js_execution_time: confidence_interval(
-            mean.js_execution_time,
-            std_deviation.js_execution_time,
-            number_of_runs,
-        ),
-        largest_contentful_paint: confidence_interval(
-            mean.largest_contentful_paint,
-            std_deviation.largest_contentful_paint,
-            number_of_runs,
-        ),
-        speed_index: confidence_interval(
-            mean.speed_index,
-            std_deviation.speed_index,
-            number_of_runs,
-        ),
-        time_to_interactive: confidence_interval(
-            mean.time_to_interactive,
-            std_deviation.time_to_interactive,
-            number_of_runs,
-        ),
-        total_blocking_time: confidence_interval(
-            mean.total_blocking_time,
-            std_deviation.total_blocking_time,
-            number_of_runs,
-        ),
-        score: confidence_interval(mean.score, std_deviation.score, number_of_runs),
-    }
-}
-
-pub fn median(list: &[f64]) -> f64 {
-    let number_of_runs: usize = list.len();
-    let index = number_of_runs / 2;
-
-    // Sort list to get the middle value
+    page_results.map_metrics(|name, values| {
+        confidence_interval(
+            *mean.metric(name).unwrap(),
+            *std_deviation.metric(name).unwrap(),
+            values.len(),
+            confidence_level,
+        )
+    })
+}
+
+fn sorted(list: &[f64]) -> Vec<f64> {
     let mut sorted_list = list.to_owned();
     sorted_list.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
-    if number_of_runs % 2 == 1 {
-        // odd
-        *sorted_list.get(index).unwrap()
+    sorted_list
+}
+
+/// Percentile of an already sorted list, interpolating linearly between the closest ranks.
+/// `rank` goes from 0 to 1.
+fn sorted_percentile(sorted_list: &[f64], rank: f64) -> f64 {
+    if sorted_list.is_empty() {
+        return f64::NAN;
+    }
+
+    let position = rank * (sorted_list.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let weight = position - lower as f64;
+
+    sorted_list[lower] + (sorted_list[upper] - sorted_list[lower]) * weight
+}
+
+pub fn percentiles(list: &[f64]) -> Percentiles {
+    // Sort list once to get every percentile
+    let sorted_list = sorted(list);
+
+    Percentiles {
+        min: sorted_percentile(&sorted_list, 0_f64),
+        p25: sorted_percentile(&sorted_list, 0.25),
+        median: sorted_percentile(&sorted_list, 0.5),
+        p75: sorted_percentile(&sorted_list, 0.75),
+        p90: sorted_percentile(&sorted_list, 0.9),
+        p95: sorted_percentile(&sorted_list, 0.95),
+        max: sorted_percentile(&sorted_list, 1_f64),
+    }
+}
+
+pub fn calculate_percentiles(page_results: &PSIResultValues) -> PSIStatisticResult<Percentiles> {
+    page_results.map(|values| percentiles(values))
+}
+
+/// Cumulative distribution function of the standard normal distribution.
+pub fn normal_cdf(z: f64) -> f64 {
+    // Complementary error function with fractional error below 1.2e-7, Numerical Recipes erfcc
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1_f64 / (1_f64 + 0.5 * x);
+    let erfc = t
+        * (-x * x - 1.26551223
+            + t * (1.00002368
+                + t * (0.37409196
+                    + t * (0.09678418
+                        + t * (-0.18628806
+                            + t * (0.27886807
+                                + t * (-1.13520398
+                                    + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+            .exp();
+
+    if z >= 0_f64 {
+        1_f64 - erfc / 2_f64
     } else {
-        // even
-        let first_median = sorted_list.get(index).unwrap();
-        let second_median = sorted_list.get(index + 1).unwrap();
-
-        (first_median + second_median) / 2_f64
-    }
-}
-
-// pub fn calculate_median(page_results: &PSIResultValues) -> PSIStatisticResult<f64> {
-// return PSIStatisticResult {
-// cumulative_layout_shift: median(&page_results.cumulative_layout_shift),
-// first_contentful_paint: median(&page_results.first_contentful_paint),
-// js_execution_time: median(&page_results.js_execution_time),
-// largest_contentful_paint: median(&page_results.largest_contentful_paint),
-// speed_index: median(&page_results.speed_index),
-// time_to_interactive: median(&page_results.time_to_interactive),
-// total_blocking_time: median(&page_results.total_blocking_time),
-// score: median(&page_results.score),
-// };
-// }
+        erfc / 2_f64
+    }
+}
+
+/// Two-sided p-value of Welch's t-test, which doesn't assume equal variances.
+pub fn welch_t_test(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() < 2 || b.len() < 2 {
+        return f64::NAN;
+    }
+
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let variance_a = variance(a, mean_a) / a.len() as f64;
+    let variance_b = variance(b, mean_b) / b.len() as f64;
+    let standard_error = (variance_a + variance_b).sqrt();
+
+    if standard_error == 0_f64 {
+        return if mean_a == mean_b { 1_f64 } else { 0_f64 };
+    }
+
+    let t = (mean_b - mean_a) / standard_error;
+    // Welch–Satterthwaite equation
+    let degrees_of_freedom = (variance_a + variance_b).powi(2)
+        / (variance_a.powi(2) / (a.len() - 1) as f64 + variance_b.powi(2) / (b.len() - 1) as f64);
+
+    2_f64 * (1_f64 - student_t_cdf(t.abs(), degrees_of_freedom))
+}
+
+/// Two-sided p-value of the Mann-Whitney U test, normal approximation with tie and continuity
+/// corrections.
+pub fn mann_whitney_u_test(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return f64::NAN;
+    }
+
+    let mut values = a
+        .iter()
+        .map(|value| (*value, true))
+        .chain(b.iter().map(|value| (*value, false)))
+        .collect::<Vec<_>>();
+    values.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap());
+
+    // Average ranks of tied values
+    let mut rank_sum_a = 0_f64;
+    let mut tie_correction = 0_f64;
+    let mut start = 0;
+    while start < values.len() {
+        let mut end = start;
+        while end + 1 < values.len() && values[end + 1].0 == values[start].0 {
+            end += 1;
+        }
+
+        let ties = (end - start + 1) as f64;
+        let rank = (start + end) as f64 / 2_f64 + 1_f64;
+        rank_sum_a += rank * values[start..=end].iter().filter(|(_, in_a)| *in_a).count() as f64;
+        tie_correction += ties.powi(3) - ties;
+        start = end + 1;
+    }
+
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let n = n_a + n_b;
+    let u_a = rank_sum_a - n_a * (n_a + 1_f64) / 2_f64;
+    let u_mean = n_a * n_b / 2_f64;
+    let u_sigma = (n_a * n_b / 12_f64 * ((n + 1_f64) - tie_correction / (n * (n - 1_f64)))).sqrt();
+
+    if u_sigma == 0_f64 {
+        return 1_f64;
+    }
+
+    let z = ((u_a - u_mean).abs() - 0.5).max(0_f64) / u_sigma;
+
+    (2_f64 * (1_f64 - normal_cdf(z))).min(1_f64)
+}
+
+/// Cohen's d of b against a, using the pooled standard deviation.
+pub fn cohens_d(a: &[f64], b: &[f64]) -> f64 {
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let pooled_variance = ((n_a - 1_f64) * variance(a, mean_a)
+        + (n_b - 1_f64) * variance(b, mean_b))
+        / (n_a + n_b - 2_f64);
+
+    (mean_b - mean_a) / pooled_variance.sqrt()
+}
+
+/// Cliff's delta of b against a: probability of b > a minus probability of b < a.
+pub fn cliffs_delta(a: &[f64], b: &[f64]) -> f64 {
+    let dominance = b
+        .iter()
+        .flat_map(|value_b| {
+            a.iter()
+                .map(move |value_a| match value_b.partial_cmp(value_a) {
+                    Some(std::cmp::Ordering::Greater) => 1_f64,
+                    Some(std::cmp::Ordering::Less) => -1_f64,
+                    _ => 0_f64,
+                })
+        })
+        .sum::<f64>();
+
+    dominance / (a.len() * b.len()) as f64
+}
+
+#[cfg(test)]
+mod statistics_tests {
+    fn assert_close(expected: f64, value: f64) {
+        assert!(
+            (expected - value).abs() < 1e-3,
+            "expected {expected}, got {value}",
+            expected = expected,
+            value = value
+        );
+    }
+
+    #[test]
+    fn std_deviation() {
+        let data = [2_f64, 4_f64, 4_f64, 4_f64, 5_f64, 5_f64, 7_f64, 9_f64];
+        let mean = super::mean(&data);
+
+        assert_close(5_f64, mean);
+        assert_close(4.571, super::variance(&data, mean));
+        assert_close(2.138, super::std_deviation(&data, mean));
+        assert!(super::variance(&[], f64::NAN).is_nan());
+        assert!(super::std_deviation(&[3_f64], 3_f64).is_nan());
+    }
+
+    #[test]
+    fn student_t_quantile() {
+        assert_close(2.776, super::student_t_quantile(0.975, 4_f64));
+        assert_close(2.093, super::student_t_quantile(0.975, 19_f64));
+        assert_close(1.729, super::student_t_quantile(0.95, 19_f64));
+        assert_close(63.657, super::student_t_quantile(0.995, 1_f64));
+    }
+
+    #[test]
+    fn confidence_interval() {
+        let (ci_min, ci_max) = super::confidence_interval(10_f64, 2_f64, 5, 0.95);
+
+        assert_close(10_f64 - 2.776 * 2_f64 / 5_f64.sqrt(), ci_min);
+        assert_close(10_f64 + 2.776 * 2_f64 / 5_f64.sqrt(), ci_max);
+    }
+
+    #[test]
+    fn percentiles() {
+        let data = [5_f64, 1_f64, 4_f64, 2_f64, 3_f64];
+        let percentiles = super::percentiles(&data);
+
+        assert_close(1_f64, percentiles.min);
+        assert_close(2_f64, percentiles.p25);
+        assert_close(3_f64, percentiles.median);
+        assert_close(4_f64, percentiles.p75);
+        assert_close(4.6, percentiles.p90);
+        assert_close(5_f64, percentiles.max);
+        assert_close(
+            2.5,
+            super::percentiles(&[1_f64, 2_f64, 3_f64, 4_f64]).median,
+        );
+        assert_close(1.5, super::percentiles(&[1_f64, 2_f64]).median);
+    }
+
+    #[test]
+    fn significance_tests() {
+        let a = [19.8, 20.4, 19.6, 17.8, 18.5, 18.9, 18.3, 18.9, 19.5, 22.0];
+        let b = [
+            28.2, 26.6, 20.1, 23.3, 25.2, 22.1, 17.7, 27.6, 20.6, 13.7, 23.2, 17.5, 20.6, 18.0,
+            23.9, 21.6, 24.3, 20.4, 23.9, 13.3,
+        ];
+
+        assert_close(0.975, super::normal_cdf(1.96));
+        assert_close(0.0286, super::normal_cdf(-1.9));
+        // Reference values computed independently with numerical integration and erfc
+        assert_close(0.0355, super::welch_t_test(&a, &b));
+        assert_close(0.0528, super::mann_whitney_u_test(&a, &b));
+        assert_close(1_f64, super::cliffs_delta(&[1_f64, 2_f64], &[3_f64, 4_f64]));
+        assert_close(
+            -1_f64,
+            super::cliffs_delta(&[3_f64, 4_f64], &[1_f64, 2_f64]),
+        );
+    }
+}
@@ -1,28 +1,33 @@
-// use chrono::{DateTime, Utc};
+use chrono::{DateTime, Utc};
 use futures::StreamExt;
 use rand::prelude::*;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::sleep;
 use url::Url;
 
-use crate::{Audit, Audits, Categories, Category, LHResult, PSIResult, PSIResultValues, Strategy};
-
-const BUFFER_SIZE: usize = 15;
-const EMPTY_AUDIT: Audit = Audit {
-    numeric_value: 0_f64,
-};
-const EMPTY_LH_RESULT: LHResult = LHResult {
-    audits: Audits {
-        cumulative_layout_shift: EMPTY_AUDIT,
-        first_contentful_paint: EMPTY_AUDIT,
-        js_execution_time: EMPTY_AUDIT,
-        largest_contentful_paint: EMPTY_AUDIT,
-        speed_index: EMPTY_AUDIT,
-        time_to_interactive: EMPTY_AUDIT,
-        total_blocking_time: EMPTY_AUDIT,
-    },
-    categories: Categories {
-        performance: Category { score: 0_f64 },
-    },
-};
+use crate::field_data::{field_data, FieldData};
+use crate::metrics::Metric;
+use crate::outcome::{RunError, RunOutcome};
+use crate::rate_limiter::RateLimiter;
+use crate::raw_output::RawOutput;
+use crate::responses;
+use crate::{LHResult, PSIResult, PSIResultValues, PSIStatisticResult, Strategy};
+
+pub const PSI_API_BASE_URL: &str = "https://www.googleapis.com/pagespeedonline/v5/runPagespeed";
+/// Requests in flight at the same time, across every page being tested.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 15;
+/// PSI default quota is 240 queries per minute.
+pub const DEFAULT_QPS: f64 = 4_f64;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+/// Query param with a random value added to the page URL of every run, so PSI doesn't answer
+/// with a cached result.
+const CACHE_BUSTER_PARAM: &str = "__v";
 
 fn add_query_param(
     url_str: &str,
@@ -34,100 +39,563 @@ fn add_query_param(
     Ok(url.into())
 }
 
-/// This methods makes requests to google PSI API in batches with BUFFER_SIZE and add the result
-/// into a return list.
-/// This APIs has a though throttling and multiple times returns errors, so, when errors happen,
-/// this method doesn't add the failed result in the return list.
-pub async fn get_page_audits(
-    url: &str,
+/// The URL without the cache buster param of the runs, as given by the user. URLs that can't be
+/// parsed are kept as they are.
+pub fn without_cache_buster(url_str: &str) -> String {
+    let mut url = match Url::parse(url_str) {
+        Ok(url) => url,
+        Err(_) => return url_str.to_string(),
+    };
+    let pairs = url
+        .query_pairs()
+        .filter(|(name, _)| name != CACHE_BUSTER_PARAM)
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+
+    if pairs.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+
+    url.into()
+}
+
+/// Mounts the runPagespeed request url on top of `api_base_url`, so the tool can be pointed to
+/// any server exposing the same API, e.g. a local mock serving canned responses.
+fn build_request_url(
+    api_base_url: &str,
     token: &str,
-    number_of_runs: i8,
-    strategy: Strategy,
-) -> Result<PSIResultValues, reqwest::Error> {
-    let mut rng = rand::thread_rng();
-
-    let list_urls = (0..number_of_runs).map(|_| {
-        format!("https://www.googleapis.com/pagespeedonline/v5/runPagespeed?key={api_key}&url={url}&strategy={strategy}&category=performance", url = add_query_param(url, "__v", &format!("{}", rng.gen::<u32>())).unwrap(), api_key = token, strategy = strategy)
-    }).collect::<Vec<String>>();
-    let client = reqwest::Client::new();
-
-    let list_responses = futures::stream::iter(list_urls.iter().map(|url| client.get(url).send()))
-        .buffer_unordered(BUFFER_SIZE)
-        .collect::<Vec<_>>()
-        .await;
+    page_url: &str,
+    strategy: &Strategy,
+    categories: &[String],
+) -> Result<String, url::ParseError> {
+    let strategy = strategy.to_string();
+    let mut params = vec![("key", token), ("url", page_url), ("strategy", &strategy)];
+    // Repeated category params, one for each category
+    params.extend(
+        categories
+            .iter()
+            .map(|category| ("category", category.as_str())),
+    );
 
-    let mut list_audits = Vec::new();
-    for res in list_responses {
-        let audit = match res {
-            Ok(result) => match result.json::<PSIResult>().await {
-                Ok(json) => LHResult {
-                    audits: json.lighthouse_result.audits,
-                    categories: json.lighthouse_result.categories,
-                },
-                Err(error) => {
-                    println!(
-                        "Error mounting lighthouse result {site}. \n {error}",
-                        site = url,
-                        error = error
-                    );
-
-                    EMPTY_LH_RESULT
-                }
-            },
-            Err(error) => {
-                println!(
-                    "Problem mounting audits {site}. \n {error}",
-                    site = url,
-                    error = error
-                );
-
-                EMPTY_LH_RESULT
-            }
+    let url = Url::parse_with_params(api_base_url, &params)?;
+
+    Ok(url.into())
+}
+
+/// Runs of a page test, in the same order they were requested.
+pub struct PageAudits {
+    pub runs: Vec<RunOutcome>,
+    /// Metrics extracted from each run.
+    pub metrics: Vec<Metric>,
+}
+
+impl PageAudits {
+    pub fn values(&self) -> PSIResultValues {
+        map_audits(&self.results(), &self.metrics)
+    }
+
+    /// Lighthouse result of each successful run.
+    pub fn results(&self) -> Vec<&LHResult> {
+        self.runs.iter().filter_map(RunOutcome::result).collect()
+    }
+
+    /// Run index of each value of each metric, in the same order of `values`. A successful run
+    /// without a metric, e.g. an audit Lighthouse didn't report, has no value for it.
+    pub fn run_indices(&self) -> PSIStatisticResult<Vec<usize>> {
+        PSIStatisticResult::from_metrics(&self.metrics, |metric| {
+            self.runs
+                .iter()
+                .enumerate()
+                .filter(|(_, run)| {
+                    run.result()
+                        .and_then(|result| metric.value(result))
+                        .is_some()
+                })
+                .map(|(index, _)| index)
+                .collect()
+        })
+    }
+
+    pub fn success_runs(&self) -> usize {
+        self.runs.iter().filter_map(RunOutcome::result).count()
+    }
+
+    /// CrUX data is the same for every run, it's taken from the first successful one.
+    pub fn field_data(&self) -> FieldData {
+        let response = self.runs.iter().find_map(RunOutcome::response);
+
+        field_data(
+            response.and_then(|response| response.loading_experience.as_ref()),
+            response.and_then(|response| response.origin_loading_experience.as_ref()),
+        )
+    }
+
+    /// Failed runs with their run index.
+    pub fn failures(&self) -> Vec<(usize, &RunError)> {
+        self.runs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, run)| run.error().map(|error| (index, error)))
+            .collect()
+    }
+}
+
+/// Parses a runPagespeed response body. Lighthouse runtime errors are reported even when the
+/// API answers with success.
+pub fn parse_response(body: &str) -> Result<PSIResult, RunError> {
+    let json = serde_json::from_str::<serde_json::Value>(body)?;
+
+    if let Some(runtime_error) = json.pointer("/lighthouseResult/runtimeError") {
+        let field = |name: &str| {
+            runtime_error
+                .get(name)
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or_default()
+                .to_string()
         };
 
-        if audit.audits.speed_index.numeric_value == 0_f64 {
-            continue;
+        return Err(RunError::Lighthouse {
+            code: field("code"),
+            message: field("message"),
+        });
+    }
+
+    Ok(serde_json::from_value::<PSIResult>(json)?)
+}
+
+/// Retry configuration for a single PSI run. Waits grow exponentially from `base_delay` up to
+/// `max_delay`, with jitter, and never less than the `Retry-After` asked by the API.
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: RETRY_BASE_DELAY,
+            max_delay: RETRY_MAX_DELAY,
         }
+    }
 
-        list_audits.push(audit);
+    /// Delay before the next attempt, `attempt` starts at 1.
+    fn backoff_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2_u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+        // Jitter between half and the full delay to spread concurrent runs
+        let jittered = exponential.mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+
+        match retry_after {
+            Some(retry_after) => jittered.max(retry_after),
+            None => jittered,
+        }
     }
+}
 
-    Ok(map_audits(&list_audits))
+/// Throttling, server errors and network problems are worth a new attempt, a page that can't
+/// be audited or an invalid request isn't.
+pub fn is_retryable(error: &RunError) -> bool {
+    match error {
+        RunError::QuotaExceeded { .. } | RunError::Timeout | RunError::Connection { .. } => true,
+        RunError::Http { status } | RunError::Api { status, .. } => {
+            *status == 408 || *status >= 500
+        }
+        RunError::Decode { .. } | RunError::Lighthouse { .. } => false,
+    }
 }
 
-pub fn map_audits(lh_results: &[LHResult]) -> PSIResultValues {
-    return PSIResultValues {
-        cumulative_layout_shift: lh_results
-            .iter()
-            .map(|result| result.audits.cumulative_layout_shift.numeric_value)
-            .collect(),
-        first_contentful_paint: lh_results
-            .iter()
-            .map(|result| result.audits.first_contentful_paint.numeric_value)
-            .collect(),
-        js_execution_time: lh_results
-            .iter()
-            .map(|result| result.audits.js_execution_time.numeric_value)
-            .collect(),
-        largest_contentful_paint: lh_results
-            .iter()
-            .map(|result| result.audits.largest_contentful_paint.numeric_value)
-            .collect(),
-        speed_index: lh_results
-            .iter()
-            .map(|result| result.audits.speed_index.numeric_value)
-            .collect(),
-        time_to_interactive: lh_results
-            .iter()
-            .map(|result| result.audits.time_to_interactive.numeric_value)
-            .collect(),
-        total_blocking_time: lh_results
-            .iter()
-            .map(|result| result.audits.total_blocking_time.numeric_value)
-            .collect(),
-        score: lh_results
+/// Parses `Retry-After` header, in delay-seconds or HTTP-date format.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+/// PSI API client shared by every test of the process, so the rate limit and the concurrency
+/// limit are respected across pages and strategies.
+pub struct Tester {
+    client: reqwest::Client,
+    token: String,
+    api_base_url: String,
+    retry_policy: RetryPolicy,
+    rate_limiter: RateLimiter,
+    max_concurrency: usize,
+    concurrency: Semaphore,
+    categories: Vec<String>,
+    metrics: Vec<Metric>,
+    raw_output: Option<RawOutput>,
+    /// Directory where the raw body of every PSI response is saved.
+    save_responses: Option<String>,
+    /// Directory of saved responses used instead of the PSI API.
+    replay: Option<String>,
+}
+
+impl Tester {
+    pub fn new(
+        token: &str,
+        api_base_url: &str,
+        retry_policy: RetryPolicy,
+        rate_limiter: RateLimiter,
+        max_concurrency: usize,
+        categories: &[&str],
+        metrics: Vec<Metric>,
+    ) -> Result<Tester, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Tester {
+            client,
+            token: token.to_string(),
+            api_base_url: api_base_url.to_string(),
+            retry_policy,
+            rate_limiter,
+            max_concurrency,
+            concurrency: Semaphore::new(max_concurrency),
+            categories: categories
+                .iter()
+                .map(|category| category.to_string())
+                .collect(),
+            metrics,
+            raw_output: None,
+            save_responses: None,
+            replay: None,
+        })
+    }
+
+    /// Every run is also written to the raw output file.
+    pub fn with_raw_output(self, raw_output: RawOutput) -> Tester {
+        Tester {
+            raw_output: Some(raw_output),
+            ..self
+        }
+    }
+
+    pub fn raw_output(&self) -> Option<&RawOutput> {
+        self.raw_output.as_ref()
+    }
+
+    /// Every PSI response is also saved to `dir`, so it can be replayed later.
+    pub fn with_save_responses(self, dir: &str) -> Tester {
+        Tester {
+            save_responses: Some(dir.to_string()),
+            ..self
+        }
+    }
+
+    /// Pages are never requested, their runs are the responses saved to `dir`.
+    pub fn with_replay(self, dir: &str) -> Tester {
+        Tester {
+            replay: Some(dir.to_string()),
+            ..self
+        }
+    }
+
+    pub fn replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// Every run of the responses saved for the page and strategy, `None` when not replaying.
+    pub fn replay_page_audits(&self, url: &str, strategy: Strategy) -> Option<PageAudits> {
+        let dir = self.replay.as_ref()?;
+
+        Some(PageAudits {
+            runs: responses::replay(dir, url, strategy).unwrap(),
+            metrics: self.metrics.clone(),
+        })
+    }
+
+    /// Single request to PSI API, with the `Retry-After` delay when the API sent one and the
+    /// status and body of the response, if it got one.
+    async fn run_attempt(
+        &self,
+        request_url: &str,
+    ) -> (RunOutcome, Option<Duration>, Option<(StatusCode, String)>) {
+        // The semaphore is never closed
+        let _permit = self.concurrency.acquire().await.unwrap();
+        self.rate_limiter.acquire().await;
+
+        let response = match self.client.get(request_url).send().await {
+            Ok(response) => response,
+            Err(error) => return (RunOutcome::Failure(error.into()), None, None),
+        };
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(error) => return (RunOutcome::Failure(error.into()), retry_after, None),
+        };
+
+        if !status.is_success() {
+            return (
+                RunOutcome::Failure(RunError::from_response(status, &body)),
+                retry_after,
+                Some((status, body)),
+            );
+        }
+
+        match parse_response(&body) {
+            Ok(result) => (
+                RunOutcome::Success(Box::new(result)),
+                None,
+                Some((status, body)),
+            ),
+            Err(error) => (RunOutcome::Failure(error), None, Some((status, body))),
+        }
+    }
+
+    /// Runs a single PSI test, retrying retryable failures up to `max_attempts` times. The
+    /// response of the last attempt is saved to `save_path`, if any.
+    async fn run_test(&self, request_url: &str, save_path: Option<&Path>) -> RunOutcome {
+        let mut attempt = 1;
+
+        loop {
+            let (outcome, retry_after, response) = self.run_attempt(request_url).await;
+
+            match outcome.error() {
+                Some(error) if is_retryable(error) && attempt < self.retry_policy.max_attempts => {
+                    sleep(self.retry_policy.backoff_delay(attempt, retry_after)).await;
+                    attempt += 1;
+                }
+                _ => {
+                    if let (Some(save_path), Some((status, body))) = (save_path, response) {
+                        responses::save(save_path, status, &body);
+                    }
+
+                    return outcome;
+                }
+            }
+        }
+    }
+
+    /// Field data of the page, from a run of its plain URL, `None` when it failed. The runs
+    /// have a cache buster PSI has no CrUX data for, their `loadingExperience` is always the one
+    /// of the origin.
+    pub async fn get_field_data(&self, url: &str, strategy: Strategy) -> Option<FieldData> {
+        let request_url = build_request_url(
+            &self.api_base_url,
+            &self.token,
+            url,
+            &strategy,
+            &self.categories,
+        )
+        .unwrap();
+        let page_audits = PageAudits {
+            runs: vec![self.run_test(&request_url, None).await],
+            metrics: self.metrics.clone(),
+        };
+
+        (page_audits.success_runs() > 0).then(|| page_audits.field_data())
+    }
+
+    /// This methods makes requests to google PSI API in batches with max_concurrency, paced by the
+    /// rate limiter, and returns the outcome of every run.
+    /// This APIs has a though throttling and multiple times returns errors, so, each run is
+    /// retried with backoff and, when it still fails, the run is kept with its RunError instead
+    /// of the lighthouse result.
+    pub async fn get_page_audits(
+        &self,
+        url: &str,
+        number_of_runs: i8,
+        strategy: Strategy,
+    ) -> PageAudits {
+        let mut rng = rand::thread_rng();
+
+        let list_urls = (0..number_of_runs)
+            .map(|_| {
+                let page_url =
+                    add_query_param(url, CACHE_BUSTER_PARAM, &format!("{}", rng.gen::<u32>()))
+                        .unwrap();
+
+                build_request_url(
+                    &self.api_base_url,
+                    &self.token,
+                    &page_url,
+                    &strategy,
+                    &self.categories,
+                )
+                .unwrap()
+            })
+            .collect::<Vec<String>>();
+
+        // Runs requested together share the timestamp of their saved responses
+        let batch = Utc::now().timestamp_millis().to_string();
+        let save_paths = (0..list_urls.len())
+            .map(|run| {
+                self.save_responses
+                    .as_ref()
+                    .map(|dir| responses::response_path(dir, url, strategy, &batch, run))
+            })
+            .collect::<Vec<Option<PathBuf>>>();
+
+        let runs = futures::stream::iter(
+            list_urls
+                .iter()
+                .zip(&save_paths)
+                .map(|(request_url, save_path)| self.run_test(request_url, save_path.as_deref())),
+        )
+        .buffered(self.max_concurrency)
+        .collect::<Vec<_>>()
+        .await;
+
+        PageAudits {
+            runs,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+pub fn map_audits(lh_results: &[&LHResult], metrics: &[Metric]) -> PSIResultValues {
+    PSIResultValues::from_metrics(metrics, |metric| {
+        lh_results
             .iter()
-            .map(|result| result.categories.performance.score)
-            .collect(),
-    };
+            .filter_map(|result| metric.value(result))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tester_tests {
+    use std::time::Duration;
+
+    use crate::outcome::RunOutcome;
+    use crate::Strategy;
+
+    #[test]
+    fn build_request_url() {
+        assert_eq!(
+            "http://localhost:8080/runPagespeed?key=TOKEN&url=https%3A%2F%2Fwww.google.com%2F%3F__v%3D1&strategy=desktop&category=performance&category=seo",
+            super::build_request_url(
+                "http://localhost:8080/runPagespeed",
+                "TOKEN",
+                "https://www.google.com/?__v=1",
+                &Strategy::DESKTOP,
+                &["performance".to_string(), "seo".to_string()],
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn without_cache_buster() {
+        assert_eq!(
+            "https://www.google.com/",
+            super::without_cache_buster("https://www.google.com/?__v=1")
+        );
+        assert_eq!(
+            "https://www.google.com/?a=1&b=2",
+            super::without_cache_buster("https://www.google.com/?a=1&__v=1&b=2")
+        );
+        assert_eq!("not a url", super::without_cache_buster("not a url"));
+    }
+
+    #[test]
+    fn parse_response() {
+        let result = super::parse_response(include_str!("../res.json")).unwrap();
+
+        assert_eq!(
+            Some(0.93),
+            result.lighthouse_result.categories["performance"].score
+        );
+        assert!(super::field_data(result.loading_experience.as_ref(), None)
+            .page
+            .is_none());
+        assert!(matches!(
+            super::parse_response(
+                r#"{"lighthouseResult": {"runtimeError": {"code": "NO_FCP", "message": "No content"}}}"#
+            ),
+            Err(crate::outcome::RunError::Lighthouse { .. })
+        ));
+    }
+
+    #[test]
+    fn run_indices() {
+        let mut without_lcp =
+            serde_json::from_str::<serde_json::Value>(include_str!("../res.json")).unwrap();
+        without_lcp["lighthouseResult"]["audits"]
+            .as_object_mut()
+            .unwrap()
+            .remove("largest-contentful-paint");
+        let page_audits = super::PageAudits {
+            runs: vec![
+                RunOutcome::Failure(crate::outcome::RunError::Timeout),
+                RunOutcome::Success(Box::new(
+                    super::parse_response(&without_lcp.to_string()).unwrap(),
+                )),
+                RunOutcome::Success(Box::new(
+                    super::parse_response(include_str!("../res.json")).unwrap(),
+                )),
+            ],
+            metrics: crate::metrics::builtin_metrics(),
+        };
+
+        let run_indices = page_audits.run_indices();
+
+        assert_eq!(&vec![1, 2], run_indices.metric("score").unwrap());
+        assert_eq!(
+            &vec![2],
+            run_indices.metric("largest_contentful_paint").unwrap()
+        );
+        assert_eq!(
+            1,
+            page_audits
+                .values()
+                .metric("largest_contentful_paint")
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn is_retryable() {
+        let lighthouse_error = crate::outcome::RunError::from_response(
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+            r#"{"error": {"code": 500, "message": "Lighthouse returned error: NO_FCP. The page did not paint any content.", "status": "INTERNAL"}}"#,
+        );
+
+        assert!(!super::is_retryable(&lighthouse_error));
+        assert!(super::is_retryable(&crate::outcome::RunError::Http {
+            status: 503
+        }));
+    }
+
+    #[test]
+    fn backoff_delay() {
+        let retry_policy = super::RetryPolicy::new(5);
+
+        let first_delay = retry_policy.backoff_delay(1, None);
+        assert!(first_delay >= Duration::from_secs(1) && first_delay <= Duration::from_secs(2));
+
+        let capped_delay = retry_policy.backoff_delay(10, None);
+        assert!(capped_delay <= Duration::from_secs(60));
+
+        let retry_after_delay = retry_policy.backoff_delay(1, Some(Duration::from_secs(30)));
+        assert_eq!(Duration::from_secs(30), retry_after_delay);
+    }
+
+    #[test]
+    fn parse_retry_after() {
+        assert_eq!(
+            Some(Duration::from_secs(120)),
+            super::parse_retry_after("120")
+        );
+        assert_eq!(
+            None,
+            super::parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+    }
 }
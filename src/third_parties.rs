@@ -0,0 +1,163 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::statistics::{mean, percentiles, variance};
+use crate::LHResult;
+
+const THIRD_PARTY_AUDIT: &str = "third-party-summary";
+
+/// Usage of a third-party entity in a run, an item of the third-party-summary audit.
+#[derive(Debug)]
+struct EntityUsage {
+    entity: String,
+    transfer_size: f64,
+    blocking_time: f64,
+    main_thread_time: f64,
+}
+
+/// Impact of a third-party entity aggregated across runs. Runs in which the entity wasn't
+/// loaded count as zero, so the variance reflects how much TBT noise the entity adds.
+#[derive(Debug, Serialize)]
+pub struct ThirdPartySummary {
+    pub entity: String,
+    /// Runs in which the entity was loaded.
+    pub runs_loaded: usize,
+    pub runs: usize,
+    pub median_blocking_time: f64,
+    pub blocking_time_variance: f64,
+    pub median_main_thread_time: f64,
+    pub median_transfer_size: f64,
+}
+
+/// Newer Lighthouse versions report the entity name, older ones a link to it.
+fn entity_name(item: &serde_json::Value) -> Option<String> {
+    let entity = item.get("entity")?;
+
+    entity
+        .as_str()
+        .or_else(|| entity.get("text").and_then(serde_json::Value::as_str))
+        .map(str::to_string)
+}
+
+fn entity_usages(result: &LHResult) -> Vec<EntityUsage> {
+    let items = result
+        .audits
+        .get(THIRD_PARTY_AUDIT)
+        .and_then(|audit| audit.details.as_ref())
+        .map(|details| details.items.as_slice())
+        .unwrap_or_default();
+    let number = |item: &serde_json::Value, key: &str| {
+        item.get(key)
+            .and_then(serde_json::Value::as_f64)
+            .unwrap_or_default()
+    };
+
+    items
+        .iter()
+        .filter_map(|item| {
+            Some(EntityUsage {
+                entity: entity_name(item)?,
+                transfer_size: number(item, "transferSize"),
+                blocking_time: number(item, "blockingTime"),
+                main_thread_time: number(item, "mainThreadTime"),
+            })
+        })
+        .collect()
+}
+
+/// Third-party entities loaded in at least one run, the ones blocking the main thread the
+/// longest first.
+pub fn aggregate(lh_results: &[&LHResult]) -> Vec<ThirdPartySummary> {
+    let runs = lh_results.len();
+    // Entity -> usages with the index of their run
+    let mut entities: HashMap<String, Vec<(usize, EntityUsage)>> = HashMap::new();
+
+    for (run, result) in lh_results.iter().enumerate() {
+        for usage in entity_usages(result) {
+            entities
+                .entry(usage.entity.clone())
+                .or_default()
+                .push((run, usage));
+        }
+    }
+
+    let mut summaries = entities
+        .into_iter()
+        .map(|(entity, usages)| {
+            // One value per run, an entity listed twice in the same run adds up
+            let values = |value: fn(&EntityUsage) -> f64| {
+                let mut values = vec![0_f64; runs];
+                for (run, usage) in &usages {
+                    values[*run] += value(usage);
+                }
+
+                values
+            };
+            let mut runs_loaded = usages.iter().map(|(run, _)| *run).collect::<Vec<_>>();
+            runs_loaded.dedup();
+            let blocking_times = values(|usage| usage.blocking_time);
+
+            ThirdPartySummary {
+                entity,
+                runs_loaded: runs_loaded.len(),
+                runs,
+                median_blocking_time: percentiles(&blocking_times).median,
+                blocking_time_variance: variance(&blocking_times, mean(&blocking_times)),
+                median_main_thread_time: percentiles(&values(|usage| usage.main_thread_time))
+                    .median,
+                median_transfer_size: percentiles(&values(|usage| usage.transfer_size)).median,
+            }
+        })
+        .collect::<Vec<_>>();
+    summaries.sort_by(|a, b| {
+        b.median_blocking_time
+            .total_cmp(&a.median_blocking_time)
+            .then(
+                b.median_main_thread_time
+                    .total_cmp(&a.median_main_thread_time),
+            )
+            .then(a.entity.cmp(&b.entity))
+    });
+
+    summaries
+}
+
+#[cfg(test)]
+mod third_parties_tests {
+    fn with_third_parties(items: &str) -> crate::LHResult {
+        let mut response =
+            serde_json::from_str::<serde_json::Value>(include_str!("../res.json")).unwrap();
+        response["lighthouseResult"]["audits"]["third-party-summary"]["details"] =
+            serde_json::from_str(&format!(r#"{{"type": "table", "items": {items}}}"#)).unwrap();
+
+        crate::tester::parse_response(&response.to_string())
+            .unwrap()
+            .lighthouse_result
+    }
+
+    #[test]
+    fn aggregate() {
+        let first = with_third_parties(
+            r#"[
+                {"entity": "Google Tag Manager", "transferSize": 90000, "blockingTime": 120, "mainThreadTime": 300},
+                {"entity": {"type": "link", "text": "Hotjar"}, "transferSize": 20000, "blockingTime": 0, "mainThreadTime": 40}
+            ]"#,
+        );
+        let second = with_third_parties(
+            r#"[{"entity": "Google Tag Manager", "transferSize": 90000, "blockingTime": 200, "mainThreadTime": 380}]"#,
+        );
+        let third = with_third_parties("[]");
+
+        let summaries = super::aggregate(&[&first, &second, &third]);
+
+        assert_eq!(2, summaries.len());
+        assert_eq!("Google Tag Manager", summaries[0].entity);
+        assert_eq!(2, summaries[0].runs_loaded);
+        assert_eq!(3, summaries[0].runs);
+        assert_eq!(120_f64, summaries[0].median_blocking_time);
+        assert!((summaries[0].blocking_time_variance - 10_133.33).abs() < 0.01);
+        assert_eq!("Hotjar", summaries[1].entity);
+        assert_eq!(1, summaries[1].runs_loaded);
+        assert_eq!(0_f64, summaries[1].median_transfer_size);
+    }
+}
@@ -1,15 +1,7 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader, Lines};
-use std::path::Path;
-
-pub fn read_lines<P>(filename: P) -> Lines<BufReader<File>>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(filename).unwrap();
-    BufReader::new(file).lines()
-}
+use std::path::{Path, PathBuf};
 
+/// `filename` when it doesn't exist, otherwise a new file next to it, adding ` (1)`, ` (2)`...
+/// to its name before the extension, if any.
 pub fn check_file_availability(filename: &str) -> String {
     let filename_path = Path::new(filename);
 
@@ -17,28 +9,39 @@ pub fn check_file_availability(filename: &str) -> String {
         return filename.to_string();
     }
 
-    let filename_without_extension = filename_path.file_stem().unwrap().to_str().unwrap();
-    let file_extension = filename_path.extension().unwrap().to_str().unwrap();
+    let filename_without_extension = filename_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let file_extension = filename_path.extension().map(|ext| ext.to_string_lossy());
+
+    let indexed_path = |index: usize| -> PathBuf {
+        let name = match &file_extension {
+            Some(ext) => format!(
+                "{filename} ({ind}).{ext}",
+                filename = filename_without_extension,
+                ind = index,
+                ext = ext,
+            ),
+            None => format!(
+                "{filename} ({ind})",
+                filename = filename_without_extension,
+                ind = index,
+            ),
+        };
+
+        filename_path.with_file_name(name)
+    };
 
     let mut index = 1;
-    let mut new_filename = format!(
-        "{filename} ({ind}).{ext}",
-        filename = filename_without_extension,
-        ind = index,
-        ext = file_extension,
-    );
-
-    while Path::new(&*new_filename).exists() {
+    let mut new_filename = indexed_path(index);
+
+    while new_filename.exists() {
         index += 1;
-        new_filename = format!(
-            "{filename} ({ind}).{ext}",
-            filename = filename_without_extension,
-            ind = index,
-            ext = file_extension,
-        );
+        new_filename = indexed_path(index);
     }
 
-    new_filename
+    new_filename.to_string_lossy().into_owned()
 }
 
 #[cfg(test)]
@@ -46,5 +49,23 @@ mod utils_tests {
     #[test]
     fn check_file_availability() {
         assert_eq!("test.txt", super::check_file_availability("test.txt"));
+
+        let dir = std::env::temp_dir().join(format!("psi-test-utils-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let report = dir.join("rep.md");
+        let no_extension = dir.join("noext");
+        std::fs::write(&report, "").unwrap();
+        std::fs::write(&no_extension, "").unwrap();
+
+        assert_eq!(
+            dir.join("rep (1).md").to_str().unwrap(),
+            super::check_file_availability(report.to_str().unwrap())
+        );
+        assert_eq!(
+            dir.join("noext (1)").to_str().unwrap(),
+            super::check_file_availability(no_extension.to_str().unwrap())
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }